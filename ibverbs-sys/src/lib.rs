@@ -82,6 +82,22 @@ pub unsafe fn ibv_query_gid_ex(
     )
 }
 
+#[inline]
+pub unsafe fn ibv_query_gid_table(
+    context: *mut ibv_context,
+    entries: *mut ibv_gid_entry,
+    max_entries: usize,
+    flags: u32,
+) -> ssize_t {
+    _ibv_query_gid_table(
+        context,
+        entries,
+        max_entries,
+        flags,
+        size_of::<ibv_gid_entry>(),
+    )
+}
+
 #[inline]
 pub unsafe fn ibv_query_device_ex(
     context: *mut ibv_context,
@@ -215,6 +231,47 @@ pub unsafe fn ibv_free_dm(dm: *mut ibv_dm) -> ffi::c_int {
     (op)(dm)
 }
 
+#[inline]
+pub unsafe fn ibv_memcpy_to_dm(
+    dm: *mut ibv_dm,
+    dm_offset: u64,
+    host_addr: *const ffi::c_void,
+    length: usize,
+) -> ffi::c_int {
+    let op = (*dm).memcpy_to_dm.unwrap_unchecked();
+    (op)(dm, dm_offset, host_addr, length)
+}
+
+#[inline]
+pub unsafe fn ibv_memcpy_from_dm(
+    host_addr: *mut ffi::c_void,
+    dm: *mut ibv_dm,
+    dm_offset: u64,
+    length: usize,
+) -> ffi::c_int {
+    let op = (*dm).memcpy_from_dm.unwrap_unchecked();
+    (op)(host_addr, dm, dm_offset, length)
+}
+
+#[inline]
+pub unsafe fn ibv_reg_dm_mr(
+    pd: *mut ibv_pd,
+    dm: *mut ibv_dm,
+    dm_offset: u64,
+    length: usize,
+    access: ffi::c_uint,
+) -> *mut ibv_mr {
+    let vctx: *mut verbs_context = verbs_get_ctx_op!((*pd).context, reg_dm_mr);
+
+    if vctx.is_null() {
+        set_errno(libc::EOPNOTSUPP);
+        return ptr::null_mut();
+    }
+
+    let op = (*vctx).reg_dm_mr.unwrap_unchecked();
+    (op)(pd, dm, dm_offset, length, access)
+}
+
 #[inline]
 pub unsafe fn ibv_post_send(
     qp: *mut ibv_qp,
@@ -237,6 +294,27 @@ pub unsafe fn ibv_post_recv(
     op(qp, wr, bad_wr)
 }
 
+#[inline]
+pub unsafe fn ibv_post_srq_recv(
+    srq: *mut ibv_srq,
+    recv_wr: *mut ibv_recv_wr,
+    bad_recv_wr: *mut *mut ibv_recv_wr,
+) -> ffi::c_int {
+    let ctx: *mut ibv_context = (*srq).context;
+    let op = (*ctx).ops.post_srq_recv.unwrap_unchecked();
+    op(srq, recv_wr, bad_recv_wr)
+}
+
+#[inline]
+pub unsafe fn ibv_get_srq_num(srq: *mut ibv_srq, srq_num: *mut u32) -> ffi::c_int {
+    let vctx = verbs_get_ctx_op!((*srq).context, get_srq_num);
+    if vctx.is_null() {
+        return libc::EOPNOTSUPP;
+    }
+    let op = (*vctx).get_srq_num.unwrap_unchecked();
+    op(srq, srq_num)
+}
+
 #[inline]
 pub unsafe fn ibv_wr_atomic_cmp_swp(
     qp: *mut ibv_qp_ex,
@@ -412,3 +490,172 @@ pub unsafe fn ibv_create_srq_ex(
     let op = (*vctx).create_srq_ex.unwrap_unchecked();
     op(context, srq_init_attr_ex)
 }
+
+#[inline]
+pub unsafe fn ibv_start_poll(cq: *mut ibv_cq_ex, attr: *mut ibv_poll_cq_attr) -> ffi::c_int {
+    let op = (*cq).start_poll.unwrap_unchecked();
+    op(cq, attr)
+}
+
+#[inline]
+pub unsafe fn ibv_next_poll(cq: *mut ibv_cq_ex) -> ffi::c_int {
+    let op = (*cq).next_poll.unwrap_unchecked();
+    op(cq)
+}
+
+#[inline]
+pub unsafe fn ibv_end_poll(cq: *mut ibv_cq_ex) {
+    let op = (*cq).end_poll.unwrap_unchecked();
+    op(cq);
+}
+
+#[inline]
+pub unsafe fn ibv_wc_read_opcode(cq: *mut ibv_cq_ex) -> ibv_wc_opcode::Type {
+    let op = (*cq).read_opcode.unwrap_unchecked();
+    op(cq)
+}
+
+#[inline]
+pub unsafe fn ibv_wc_read_vendor_err(cq: *mut ibv_cq_ex) -> u32 {
+    let op = (*cq).read_vendor_err.unwrap_unchecked();
+    op(cq)
+}
+
+#[inline]
+pub unsafe fn ibv_wc_read_byte_len(cq: *mut ibv_cq_ex) -> u32 {
+    let op = (*cq).read_byte_len.unwrap_unchecked();
+    op(cq)
+}
+
+#[inline]
+pub unsafe fn ibv_wc_read_imm_data(cq: *mut ibv_cq_ex) -> __be32 {
+    let op = (*cq).read_imm_data.unwrap_unchecked();
+    op(cq)
+}
+
+#[inline]
+pub unsafe fn ibv_wc_read_qp_num(cq: *mut ibv_cq_ex) -> u32 {
+    let op = (*cq).read_qp_num.unwrap_unchecked();
+    op(cq)
+}
+
+#[inline]
+pub unsafe fn ibv_wc_read_src_qp(cq: *mut ibv_cq_ex) -> u32 {
+    let op = (*cq).read_src_qp.unwrap_unchecked();
+    op(cq)
+}
+
+#[inline]
+pub unsafe fn ibv_wc_read_wc_flags(cq: *mut ibv_cq_ex) -> ffi::c_uint {
+    let op = (*cq).read_wc_flags.unwrap_unchecked();
+    op(cq)
+}
+
+#[inline]
+pub unsafe fn ibv_wc_read_slid(cq: *mut ibv_cq_ex) -> u32 {
+    let op = (*cq).read_slid.unwrap_unchecked();
+    op(cq)
+}
+
+#[inline]
+pub unsafe fn ibv_wc_read_sl(cq: *mut ibv_cq_ex) -> u8 {
+    let op = (*cq).read_sl.unwrap_unchecked();
+    op(cq)
+}
+
+#[inline]
+pub unsafe fn ibv_wc_read_dlid_path_bits(cq: *mut ibv_cq_ex) -> u8 {
+    let op = (*cq).read_dlid_path_bits.unwrap_unchecked();
+    op(cq)
+}
+
+#[inline]
+pub unsafe fn ibv_wc_read_cvlan(cq: *mut ibv_cq_ex) -> u16 {
+    let op = (*cq).read_cvlan.unwrap_unchecked();
+    op(cq)
+}
+
+#[inline]
+pub unsafe fn ibv_wc_read_flow_tag(cq: *mut ibv_cq_ex) -> u32 {
+    let op = (*cq).read_flow_tag.unwrap_unchecked();
+    op(cq)
+}
+
+#[inline]
+pub unsafe fn ibv_wc_read_completion_ts(cq: *mut ibv_cq_ex) -> u64 {
+    let op = (*cq).read_completion_ts.unwrap_unchecked();
+    op(cq)
+}
+
+#[inline]
+pub unsafe fn ibv_wc_read_completion_wallclock_ns(cq: *mut ibv_cq_ex) -> u64 {
+    let op = (*cq).read_completion_wallclock_ns.unwrap_unchecked();
+    op(cq)
+}
+
+#[inline]
+pub unsafe fn ibv_query_rt_values_ex(
+    context: *mut ibv_context,
+    values: *mut ibv_values_ex,
+) -> ffi::c_int {
+    let vctx = verbs_get_ctx_op!(context, query_rt_values);
+    if vctx.is_null() {
+        return libc::EOPNOTSUPP;
+    }
+    let op = (*vctx).query_rt_values.unwrap_unchecked();
+    op(context, values)
+}
+
+/// `IB_UVERBS_ADVISE_MR_FLAG_FLUSH` is not reachable from the allowlist
+pub const IBV_ADVISE_MR_FLAG_FLUSH: u32 = 1 << 0;
+
+#[inline]
+pub unsafe fn ibv_advise_mr(
+    pd: *mut ibv_pd,
+    advice: ib_uverbs_advise_mr_advice,
+    flags: u32,
+    sg_list: *mut ibv_sge,
+    num_sge: u32,
+) -> ffi::c_int {
+    let vctx = verbs_get_ctx_op!((*pd).context, advise_mr);
+    if vctx.is_null() {
+        return libc::EOPNOTSUPP;
+    }
+    let op = (*vctx).advise_mr.unwrap_unchecked();
+    op(pd, advice, flags, sg_list, num_sge)
+}
+
+#[inline]
+pub unsafe fn ibv_open_xrcd(
+    context: *mut ibv_context,
+    xrcd_init_attr: *mut ibv_xrcd_init_attr,
+) -> *mut ibv_xrcd {
+    let vctx = verbs_get_ctx_op!(context, open_xrcd);
+    if vctx.is_null() {
+        set_errno(libc::EOPNOTSUPP);
+        return ptr::null_mut();
+    }
+    let op = (*vctx).open_xrcd.unwrap_unchecked();
+    op(context, xrcd_init_attr)
+}
+
+#[inline]
+pub unsafe fn ibv_close_xrcd(xrcd: *mut ibv_xrcd) -> ffi::c_int {
+    let vctx = verbs_get_ctx((*xrcd).context);
+    let op = (*vctx).close_xrcd.unwrap_unchecked();
+    op(xrcd)
+}
+
+#[inline]
+pub unsafe fn ibv_open_qp(
+    context: *mut ibv_context,
+    qp_open_attr: *mut ibv_qp_open_attr,
+) -> *mut ibv_qp {
+    let vctx = verbs_get_ctx_op!(context, open_qp);
+    if vctx.is_null() {
+        set_errno(libc::EOPNOTSUPP);
+        return ptr::null_mut();
+    }
+    let op = (*vctx).open_qp.unwrap_unchecked();
+    op(context, qp_open_attr)
+}
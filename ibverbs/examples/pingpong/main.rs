@@ -1,3 +1,535 @@
+//! A port of `ibv_rc_pingpong` from rdma-core.
+//!
+//! It uses the same TCP exchange format, so it interoperates with the C tool.
+//!
+//! ```text
+//! server: pingpong [options]
+//! client: pingpong [options] <host>
+//! ```
+
+use ibverbs::cc::CompChannel;
+use ibverbs::cq::{CompletionQueue, CompletionQueueOptions};
+use ibverbs::ctx::Context;
+use ibverbs::device::{DeviceList, Gid, GidEntry, LinkLayer, Mtu, PortAttr};
+use ibverbs::mr::{AccessFlags, MemoryRegion};
+use ibverbs::pd::ProtectionDomain;
+use ibverbs::qp::{
+    InitAttr, PathAttr, QueryOptions, QueuePair, QueuePairCapacity, QueuePairType, RemoteEndpoint,
+    RtrAttr, RtsAttr,
+};
+use ibverbs::wc::{WorkCompletion, WorkCompletionError};
+use ibverbs::wr::{Opcode, RecvRequest, SendFlags, SendRequest, Sge};
+
+use std::error::Error;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use std::{env, mem, process};
+
+type Result<T, E = Box<dyn Error>> = std::result::Result<T, E>;
+
+const RECV_WRID: u64 = 1;
+const SEND_WRID: u64 = 2;
+
+/// The size of `"0000:000000:000000:00000000000000000000000000000000\0"`
+const MSG_LEN: usize = 52;
+const DONE_MSG: &[u8; 5] = b"done\0";
+
+struct Options {
+    port: u16,
+    ib_devname: Option<String>,
+    ib_port: u8,
+    size: usize,
+    mtu: Mtu,
+    rx_depth: u32,
+    iters: u32,
+    sl: u8,
+    use_event: bool,
+    gidx: Option<u8>,
+    servername: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            port: 18515,
+            ib_devname: None,
+            ib_port: 1,
+            size: 4096,
+            mtu: Mtu::Mtu1024,
+            rx_depth: 500,
+            iters: 1000,
+            sl: 0,
+            use_event: false,
+            gidx: None,
+            servername: None,
+        }
+    }
+}
+
+fn usage(argv0: &str) {
+    println!("Usage:");
+    println!("  {argv0}            start a server and wait for connection");
+    println!("  {argv0} <host>     connect to server at <host>");
+    println!();
+    println!("Options:");
+    println!("  -p, --port=<port>      listen on/connect to port <port> (default 18515)");
+    println!("  -d, --ib-dev=<dev>     use IB device <dev> (default first device found)");
+    println!("  -i, --ib-port=<port>   use port <port> of IB device (default 1)");
+    println!("  -s, --size=<size>      size of message to exchange (default 4096)");
+    println!("  -m, --mtu=<size>       path MTU (default 1024)");
+    println!("  -r, --rx-depth=<dep>   number of receives to post at a time (default 500)");
+    println!("  -n, --iters=<iters>    number of exchanges (default 1000)");
+    println!("  -l, --sl=<sl>          service level value");
+    println!("  -e, --events           sleep on CQ events (default poll)");
+    println!("  -g, --gid-idx=<gid index> local port gid index");
+}
+
+fn mtu_from_size(size: u32) -> Option<Mtu> {
+    match size {
+        256 => Some(Mtu::Mtu256),
+        512 => Some(Mtu::Mtu512),
+        1024 => Some(Mtu::Mtu1024),
+        2048 => Some(Mtu::Mtu2048),
+        4096 => Some(Mtu::Mtu4096),
+        _ => None,
+    }
+}
+
+fn parse_args() -> Result<Options> {
+    let mut args = env::args();
+    let argv0 = args.next().unwrap_or_else(|| "pingpong".to_owned());
+    let mut opts = Options::default();
+
+    while let Some(arg) = args.next() {
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) if arg.starts_with("--") => {
+                (name.to_owned(), Some(value.to_owned()))
+            }
+            _ => (arg.clone(), None),
+        };
+        let mut value = || -> Result<String> {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("missing value for {name}").into())
+        };
+        match name.as_str() {
+            "-p" | "--port" => opts.port = value()?.parse()?,
+            "-d" | "--ib-dev" => opts.ib_devname = Some(value()?),
+            "-i" | "--ib-port" => opts.ib_port = value()?.parse()?,
+            "-s" | "--size" => opts.size = value()?.parse()?,
+            "-m" | "--mtu" => {
+                opts.mtu = mtu_from_size(value()?.parse()?).ok_or("invalid mtu")?;
+            }
+            "-r" | "--rx-depth" => opts.rx_depth = value()?.parse()?,
+            "-n" | "--iters" => opts.iters = value()?.parse()?,
+            "-l" | "--sl" => opts.sl = value()?.parse()?,
+            "-e" | "--events" => opts.use_event = true,
+            "-g" | "--gid-idx" => opts.gidx = Some(value()?.parse()?),
+            "-h" | "--help" => {
+                usage(&argv0);
+                process::exit(0);
+            }
+            _ if !arg.starts_with('-') && opts.servername.is_none() => opts.servername = Some(arg),
+            _ => {
+                usage(&argv0);
+                process::exit(1);
+            }
+        }
+    }
+    Ok(opts)
+}
+
+/// The destination exchanged with the peer
+struct Dest {
+    lid: u16,
+    qpn: u32,
+    psn: u32,
+    gid: Gid,
+}
+
+impl Dest {
+    fn to_msg(&self) -> [u8; MSG_LEN] {
+        let wire_gid: String = self
+            .gid
+            .as_bytes()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        let text = format!(
+            "{:04x}:{:06x}:{:06x}:{wire_gid}",
+            self.lid, self.qpn, self.psn
+        );
+        let mut msg = [0; MSG_LEN];
+        msg[..text.len()].copy_from_slice(text.as_bytes());
+        msg
+    }
+
+    fn from_msg(msg: &[u8; MSG_LEN]) -> Result<Self> {
+        let end = msg.iter().position(|&b| b == 0).unwrap_or(MSG_LEN);
+        let text = std::str::from_utf8(&msg[..end])?;
+        let mut fields = text.split(':');
+        let mut field = || fields.next().ok_or("malformed destination");
+        let lid = u16::from_str_radix(field()?, 16)?;
+        let qpn = u32::from_str_radix(field()?, 16)?;
+        let psn = u32::from_str_radix(field()?, 16)?;
+        let wire_gid = field()?;
+        if wire_gid.len() != 32 {
+            return Err("malformed gid".into());
+        }
+        let mut gid = [0; 16];
+        for (i, byte) in gid.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&wire_gid[i * 2..i * 2 + 2], 16)?;
+        }
+        Ok(Self {
+            lid,
+            qpn,
+            psn,
+            gid: Gid::from_bytes(gid),
+        })
+    }
+
+    fn print(&self, side: &str) {
+        println!(
+            "  {side} address:  LID 0x{:04x}, QPN 0x{:06x}, PSN 0x{:06x}, GID {}",
+            self.lid,
+            self.qpn,
+            self.psn,
+            self.gid.to_ipv6_addr()
+        );
+    }
+}
+
+fn client_exch_dest(servername: &str, port: u16, my_dest: &Dest) -> Result<Dest> {
+    let mut stream = TcpStream::connect((servername, port))
+        .map_err(|e| format!("Couldn't connect to {servername}:{port}: {e}"))?;
+    stream.write_all(&my_dest.to_msg())?;
+    let mut msg = [0; MSG_LEN];
+    stream.read_exact(&mut msg)?;
+    stream.write_all(DONE_MSG)?;
+    Dest::from_msg(&msg)
+}
+
+fn server_exch_dest(
+    port: u16,
+    my_dest: &Dest,
+    connect: impl FnOnce(&Dest) -> Result<()>,
+) -> Result<Dest> {
+    let listener = TcpListener::bind(("0.0.0.0", port))
+        .map_err(|e| format!("Couldn't listen to port {port}: {e}"))?;
+    let (mut stream, _) = listener.accept()?;
+    let mut msg = [0; MSG_LEN];
+    stream.read_exact(&mut msg)?;
+    let rem_dest = Dest::from_msg(&msg)?;
+    connect(&rem_dest)?;
+    stream.write_all(&my_dest.to_msg())?;
+    let mut done = [0; DONE_MSG.len()];
+    stream.read_exact(&mut done)?;
+    Ok(rem_dest)
+}
+
+fn connect_qp(qp: &QueuePair, opts: &Options, my_psn: u32, dest: &Dest) -> Result<()> {
+    let has_gid = dest.gid.interface_id() != 0;
+    qp.to_rtr(&RtrAttr {
+        remote: Some(RemoteEndpoint {
+            qp_num: dest.qpn,
+            psn: dest.psn,
+            lid: dest.lid,
+            gid: has_gid.then_some(dest.gid),
+        }),
+        path: Some(PathAttr {
+            port_num: opts.ib_port,
+            mtu: opts.mtu,
+            sgid_index: opts.gidx.unwrap_or(0),
+            service_level: opts.sl,
+            hop_limit: 1,
+            traffic_class: 0,
+            flow_label: 0,
+        }),
+        max_dest_rd_atomic: Some(1),
+        min_rnr_timer: Some(12),
+    })
+    .map_err(|e| format!("Failed to modify QP to RTR: {e}"))?;
+    qp.to_rts(&RtsAttr {
+        sq_psn: my_psn,
+        timeout: Some(14),
+        retry_cnt: Some(7),
+        rnr_retry: Some(7),
+        max_rd_atomic: Some(1),
+    })
+    .map_err(|e| format!("Failed to modify QP to RTS: {e}"))?;
+    Ok(())
+}
+
+struct PingPong {
+    cq: CompletionQueue,
+    qp: QueuePair,
+    channel: Option<CompChannel>,
+    sge: Sge,
+    send_flags: SendFlags,
+}
+
+impl PingPong {
+    fn post_recv(&self, n: u32) -> u32 {
+        let mut wr = RecvRequest::zeroed();
+        wr.id(RECV_WRID).sg_list(std::slice::from_ref(&self.sge));
+        for i in 0..n {
+            // SAFETY: the buffer is registered and alive until the queue pair is destroyed
+            if unsafe { self.qp.post_recv(&wr) }.is_err() {
+                return i;
+            }
+        }
+        n
+    }
+
+    fn post_send(&self) -> Result<()> {
+        let mut wr = SendRequest::zeroed();
+        wr.id(SEND_WRID)
+            .sg_list(std::slice::from_ref(&self.sge))
+            .opcode(Opcode::Send)
+            .send_flags(self.send_flags);
+        // SAFETY: the buffer is registered and alive until the queue pair is destroyed
+        unsafe { self.qp.post_send(&wr)? };
+        Ok(())
+    }
+}
+
 fn main() {
-    println!("Running pingpong example...");
+    if let Err(err) = run() {
+        eprintln!("{err}");
+        process::exit(1);
+    }
+}
+
+#[allow(clippy::too_many_lines)]
+fn run() -> Result<()> {
+    let opts = parse_args()?;
+
+    let dev_list =
+        DeviceList::available().map_err(|e| format!("Failed to get IB devices list: {e}"))?;
+    let device = match opts.ib_devname {
+        Some(ref name) => dev_list
+            .iter()
+            .find(|dev| dev.name() == name)
+            .ok_or_else(|| format!("IB device {name} not found"))?,
+        None => dev_list.first().ok_or("No IB devices found")?,
+    };
+
+    let ctx = Context::open(device)
+        .map_err(|e| format!("Couldn't get context for {}: {e}", device.name()))?;
+    let channel = if opts.use_event {
+        Some(
+            CompChannel::create(&ctx)
+                .map_err(|e| format!("Couldn't create completion channel: {e}"))?,
+        )
+    } else {
+        None
+    };
+    let pd = ProtectionDomain::alloc(&ctx).map_err(|e| format!("Couldn't allocate PD: {e}"))?;
+
+    let mut buf = vec![0x7b_u8; opts.size];
+    // SAFETY: the buffer outlives the memory region
+    let mr = unsafe {
+        MemoryRegion::register(
+            &pd,
+            buf.as_mut_ptr(),
+            buf.len(),
+            AccessFlags::LOCAL_WRITE,
+            (),
+        )
+    }
+    .map_err(|e| format!("Couldn't register MR: {e}"))?;
+
+    let cq = {
+        let mut options = CompletionQueueOptions::default();
+        options.cqe(opts.rx_depth as usize + 1);
+        if let Some(ref cc) = channel {
+            options.channel(cc);
+        }
+        CompletionQueue::create(&ctx, options).map_err(|e| format!("Couldn't create CQ: {e}"))?
+    };
+
+    let qp = {
+        let mut options = QueuePair::options();
+        options
+            .send_cq(&cq)
+            .recv_cq(&cq)
+            .pd(&pd)
+            .qp_type(QueuePairType::RC)
+            .cap(QueuePairCapacity {
+                max_send_wr: 1,
+                max_recv_wr: opts.rx_depth,
+                max_send_sge: 1,
+                max_recv_sge: 1,
+                max_inline_data: 0,
+            });
+        QueuePair::create(&ctx, options).map_err(|e| format!("Couldn't create QP: {e}"))?
+    };
+
+    let mut send_flags = SendFlags::SIGNALED;
+    let mut query = QueryOptions::default();
+    query.cap();
+    let attr = qp.query(query)?;
+    if let Some(cap) = attr.cap() {
+        if cap.max_inline_data as usize >= opts.size {
+            send_flags |= SendFlags::INLINE;
+        }
+    }
+
+    qp.to_init(&InitAttr {
+        port_num: opts.ib_port,
+        pkey_index: 0,
+        access_flags: Some(AccessFlags::empty()),
+        qkey: None,
+    })
+    .map_err(|e| format!("Failed to modify QP to INIT: {e}"))?;
+
+    let pp = PingPong {
+        cq,
+        qp,
+        channel,
+        sge: Sge {
+            addr: mr.addr_u64(),
+            length: u32::try_from(opts.size)?,
+            lkey: mr.lkey(),
+        },
+        send_flags,
+    };
+
+    let mut routs = pp.post_recv(opts.rx_depth);
+    if routs < opts.rx_depth {
+        return Err(format!("Couldn't post receive ({routs})").into());
+    }
+
+    if pp.channel.is_some() {
+        pp.cq
+            .req_notify_all()
+            .map_err(|e| format!("Couldn't request CQ notification: {e}"))?;
+    }
+
+    let port_attr =
+        PortAttr::query(&ctx, opts.ib_port).map_err(|e| format!("Couldn't get port info: {e}"))?;
+    if port_attr.link_layer() != LinkLayer::Ethernet && port_attr.lid() == 0 {
+        return Err("Couldn't get local LID".into());
+    }
+
+    let gid = match opts.gidx {
+        Some(gidx) => GidEntry::query(&ctx, opts.ib_port.into(), gidx.into())
+            .map_err(|e| format!("can't read sgid of index {gidx}: {e}"))?
+            .gid(),
+        None => Gid::from_bytes([0; 16]),
+    };
+
+    let my_dest = Dest {
+        lid: port_attr.lid(),
+        qpn: pp.qp.qp_num(),
+        psn: random_psn(),
+        gid,
+    };
+    my_dest.print("local");
+
+    let rem_dest = match opts.servername {
+        Some(ref servername) => {
+            let rem_dest = client_exch_dest(servername, opts.port, &my_dest)?;
+            connect_qp(&pp.qp, &opts, my_dest.psn, &rem_dest)?;
+            rem_dest
+        }
+        None => server_exch_dest(opts.port, &my_dest, |rem_dest| {
+            connect_qp(&pp.qp, &opts, my_dest.psn, rem_dest)
+        })?,
+    };
+    rem_dest.print("remote");
+
+    let mut pending = RECV_WRID;
+    if opts.servername.is_some() {
+        pp.post_send()
+            .map_err(|e| format!("Couldn't post send: {e}"))?;
+        pending |= SEND_WRID;
+    }
+
+    let start = Instant::now();
+    let mut rcnt = 0;
+    let mut scnt = 0;
+    let mut num_cq_events = 0;
+    let mut wc_buf: [mem::MaybeUninit<WorkCompletion>; 2] =
+        [const { mem::MaybeUninit::uninit() }; 2];
+
+    while rcnt < opts.iters || scnt < opts.iters {
+        if let Some(ref cc) = pp.channel {
+            cc.wait_cq_event()
+                .map_err(|e| format!("Failed to get cq_event: {e}"))?;
+            num_cq_events += 1;
+            pp.cq
+                .req_notify_all()
+                .map_err(|e| format!("Couldn't request CQ notification: {e}"))?;
+        }
+
+        let mut wcs = pp
+            .cq
+            .poll(&mut wc_buf)
+            .map_err(|e| format!("poll CQ failed: {e}"))?;
+        while pp.channel.is_none() && wcs.is_empty() {
+            wcs = pp
+                .cq
+                .poll(&mut wc_buf)
+                .map_err(|e| format!("poll CQ failed: {e}"))?;
+        }
+
+        for wc in wcs.iter() {
+            WorkCompletionError::result(wc.status())
+                .map_err(|e| format!("Failed status {e} for wr_id {}", wc.wr_id()))?;
+
+            match wc.wr_id() {
+                SEND_WRID => scnt += 1,
+                RECV_WRID => {
+                    routs -= 1;
+                    if routs <= 1 {
+                        routs += pp.post_recv(opts.rx_depth - routs);
+                        if routs < opts.rx_depth {
+                            return Err(format!("Couldn't post receive ({routs})").into());
+                        }
+                    }
+                    rcnt += 1;
+                }
+                wr_id => return Err(format!("Completion for unknown wr_id {wr_id}").into()),
+            }
+
+            pending &= !wc.wr_id();
+            if scnt < opts.iters && pending == 0 {
+                pp.post_send()
+                    .map_err(|e| format!("Couldn't post send: {e}"))?;
+                pending = RECV_WRID | SEND_WRID;
+            }
+        }
+    }
+
+    let usec = start.elapsed().as_secs_f64() * 1e6;
+    let bytes = opts.size as u64 * u64::from(opts.iters) * 2;
+    println!(
+        "{bytes} bytes in {:.2} seconds = {:.2} Mbit/sec",
+        usec / 1e6,
+        bytes as f64 * 8.0 / usec
+    );
+    println!(
+        "{} iters in {:.2} seconds = {:.2} usec/iter",
+        opts.iters,
+        usec / 1e6,
+        usec / f64::from(opts.iters)
+    );
+
+    pp.cq.ack_cq_events(num_cq_events);
+
+    drop(pp);
+    drop(mr);
+    drop(buf);
+    Ok(())
+}
+
+/// Returns a 24-bit packet sequence number.
+fn random_psn() -> u32 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos());
+    (nanos ^ process::id().rotate_left(12)) & 0x00ff_ffff
 }
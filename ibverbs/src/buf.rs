@@ -0,0 +1,486 @@
+//! Buffers which can be owned by registered memory regions.
+
+use crate::error::last_error;
+use crate::mr::{AccessFlags, OwnedMemoryRegion};
+use crate::pd::ProtectionDomain;
+use crate::utils::ptr_to_addr;
+
+use std::alloc::{self, Layout};
+use std::{fs, io, ptr, slice};
+
+/// A buffer which can be owned by [`OwnedMemoryRegion`].
+///
+/// # Safety
+/// + the buffer must contain `len` initialized items starting at `as_ptr`
+/// + the items must stay at the same address when the buffer is moved
+/// + every bit pattern must be a valid item, because the items can be written by the remote side
+pub unsafe trait RegisteredBuffer: Send + Sync + 'static {
+    type Item;
+
+    fn as_ptr(&self) -> *const Self::Item;
+
+    fn as_mut_ptr(&mut self) -> *mut Self::Item;
+
+    fn len(&self) -> usize;
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(not(feature = "bytemuck"))]
+/// SAFETY: heap allocation of bytes
+unsafe impl RegisteredBuffer for Vec<u8> {
+    type Item = u8;
+
+    #[inline]
+    fn as_ptr(&self) -> *const u8 {
+        self.as_slice().as_ptr()
+    }
+
+    #[inline]
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.as_mut_slice().as_mut_ptr()
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+}
+
+#[cfg(not(feature = "bytemuck"))]
+/// SAFETY: heap allocation of bytes
+unsafe impl RegisteredBuffer for Box<[u8]> {
+    type Item = u8;
+
+    #[inline]
+    fn as_ptr(&self) -> *const u8 {
+        (**self).as_ptr()
+    }
+
+    #[inline]
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        (**self).as_mut_ptr()
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+}
+
+#[cfg(feature = "bytemuck")]
+/// SAFETY: heap allocation of plain old data
+unsafe impl<T: bytemuck::Pod + Send + Sync> RegisteredBuffer for Vec<T> {
+    type Item = T;
+
+    #[inline]
+    fn as_ptr(&self) -> *const T {
+        self.as_slice().as_ptr()
+    }
+
+    #[inline]
+    fn as_mut_ptr(&mut self) -> *mut T {
+        self.as_mut_slice().as_mut_ptr()
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+}
+
+#[cfg(feature = "bytemuck")]
+/// SAFETY: heap allocation of plain old data
+unsafe impl<T: bytemuck::Pod + Send + Sync> RegisteredBuffer for Box<[T]> {
+    type Item = T;
+
+    #[inline]
+    fn as_ptr(&self) -> *const T {
+        (**self).as_ptr()
+    }
+
+    #[inline]
+    fn as_mut_ptr(&mut self) -> *mut T {
+        (**self).as_mut_ptr()
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+}
+
+/// A zeroed buffer aligned to the page size
+pub struct AlignedBuffer {
+    ptr: ptr::NonNull<u8>,
+    len: usize,
+    layout: Layout,
+}
+
+/// SAFETY: owned type
+unsafe impl Send for AlignedBuffer {}
+/// SAFETY: owned type
+unsafe impl Sync for AlignedBuffer {}
+
+impl AlignedBuffer {
+    /// Allocates a zeroed buffer of `len` bytes aligned to the page size.
+    ///
+    /// # Panics
+    /// + if the size overflows
+    #[inline]
+    #[must_use]
+    pub fn new(len: usize) -> Self {
+        Self::with_alignment(len, page_size())
+    }
+
+    /// Allocates a zeroed buffer of `len` bytes aligned to `align`.
+    ///
+    /// # Panics
+    /// + if `align` is not a power of two
+    /// + if the size overflows
+    #[inline]
+    #[must_use]
+    pub fn with_alignment(len: usize, align: usize) -> Self {
+        let layout = Layout::from_size_align(len.max(1), align).unwrap();
+        // SAFETY: the layout has a non-zero size
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let Some(ptr) = ptr::NonNull::new(ptr) else {
+            alloc::handle_alloc_error(layout)
+        };
+        Self { ptr, len, layout }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn as_slice(&self) -> &[u8] {
+        // SAFETY: initialized allocation
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: initialized allocation
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for AlignedBuffer {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: allocated with the same layout
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
+
+/// SAFETY: heap allocation of bytes
+unsafe impl RegisteredBuffer for AlignedBuffer {
+    type Item = u8;
+
+    #[inline]
+    fn as_ptr(&self) -> *const u8 {
+        self.ptr.as_ptr()
+    }
+
+    #[inline]
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// The size of huge pages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HugePageSize {
+    Size2M,
+    Size1G,
+}
+
+impl HugePageSize {
+    #[inline]
+    #[must_use]
+    pub fn bytes(self) -> usize {
+        match self {
+            HugePageSize::Size2M => 1 << 21,
+            HugePageSize::Size1G => 1 << 30,
+        }
+    }
+
+    fn map_flag(self) -> libc::c_int {
+        match self {
+            HugePageSize::Size2M => libc::MAP_HUGE_2MB,
+            HugePageSize::Size1G => libc::MAP_HUGE_1GB,
+        }
+    }
+}
+
+/// The kind of pages backing a [`HugePageBuffer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageKind {
+    /// Huge pages from hugetlbfs
+    HugeTlb(HugePageSize),
+    /// Normal pages advised to be backed by transparent huge pages,
+    /// which the kernel may still ignore
+    TransparentAdvised,
+    /// Normal pages
+    Normal,
+}
+
+/// A zeroed anonymous mapping backed by huge pages if available
+pub struct HugePageBuffer {
+    ptr: ptr::NonNull<u8>,
+    len: usize,
+    map_len: usize,
+    kind: PageKind,
+}
+
+/// SAFETY: owned type
+unsafe impl Send for HugePageBuffer {}
+/// SAFETY: owned type
+unsafe impl Sync for HugePageBuffer {}
+
+impl HugePageBuffer {
+    /// Maps a buffer of `len` bytes.
+    ///
+    /// It tries hugetlbfs pages of `size` first, then transparent huge pages,
+    /// and falls back to normal pages.
+    /// Transparent huge pages are 2 MiB, so the fallback mapping is rounded up to 2 MiB.
+    #[inline]
+    pub fn alloc(len: usize, size: HugePageSize) -> io::Result<Self> {
+        let map_len = round_up(len.max(1), size.bytes())?;
+
+        let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_HUGETLB | size.map_flag();
+        // SAFETY: anonymous mapping
+        if let Ok(ptr) = unsafe { mmap(map_len, flags) } {
+            let kind = PageKind::HugeTlb(size);
+            return Ok(Self {
+                ptr,
+                len,
+                map_len,
+                kind,
+            });
+        }
+
+        Self::alloc_fallback(len)
+    }
+
+    /// Maps normal pages aligned for transparent huge pages,
+    /// and advises the kernel to use them if they are enabled.
+    fn alloc_fallback(len: usize) -> io::Result<Self> {
+        let thp_size = HugePageSize::Size2M.bytes();
+        let map_len = round_up(len.max(1), thp_size)?;
+        // SAFETY: anonymous mapping
+        let ptr = unsafe { mmap_aligned(map_len, thp_size)? };
+        // SAFETY: ffi
+        let ret = unsafe { libc::madvise(ptr.as_ptr().cast(), map_len, libc::MADV_HUGEPAGE) };
+        let kind = if ret == 0 && thp_enabled() {
+            PageKind::TransparentAdvised
+        } else {
+            PageKind::Normal
+        };
+        Ok(Self {
+            ptr,
+            len,
+            map_len,
+            kind,
+        })
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn page_kind(&self) -> PageKind {
+        self.kind
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn as_slice(&self) -> &[u8] {
+        // SAFETY: initialized mapping
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: initialized mapping
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+
+    /// Registers the buffer as a memory region.
+    ///
+    /// [`AccessFlags::HUGETLB`] is set if the buffer is backed by hugetlbfs pages.
+    #[inline]
+    pub fn register(
+        self,
+        pd: &ProtectionDomain,
+        mut access_flags: AccessFlags,
+    ) -> io::Result<OwnedMemoryRegion<Self>> {
+        if let PageKind::HugeTlb(_) = self.kind {
+            access_flags |= AccessFlags::HUGETLB;
+        }
+        OwnedMemoryRegion::register(pd, self, access_flags)
+    }
+}
+
+impl Drop for HugePageBuffer {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: ffi
+        unsafe {
+            let ret = libc::munmap(self.ptr.as_ptr().cast(), self.map_len);
+            assert_eq!(ret, 0);
+        }
+    }
+}
+
+/// SAFETY: anonymous mapping of bytes
+unsafe impl RegisteredBuffer for HugePageBuffer {
+    type Item = u8;
+
+    #[inline]
+    fn as_ptr(&self) -> *const u8 {
+        self.ptr.as_ptr()
+    }
+
+    #[inline]
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+const THP_ENABLED: &str = "/sys/kernel/mm/transparent_hugepage/enabled";
+
+/// Returns whether transparent huge pages are enabled for advised mappings.
+fn thp_enabled() -> bool {
+    fs::read_to_string(THP_ENABLED).is_ok_and(|mode| thp_mode_allows_advice(&mode))
+}
+
+/// Parses the THP mode such as `always [madvise] never`, where the selected one is bracketed.
+fn thp_mode_allows_advice(mode: &str) -> bool {
+    mode.split_whitespace()
+        .any(|m| m == "[always]" || m == "[madvise]")
+}
+
+fn round_up(len: usize, align: usize) -> io::Result<usize> {
+    len.checked_next_multiple_of(align)
+        .ok_or_else(|| io::Error::from(io::ErrorKind::OutOfMemory))
+}
+
+unsafe fn mmap(len: usize, flags: libc::c_int) -> io::Result<ptr::NonNull<u8>> {
+    let prot = libc::PROT_READ | libc::PROT_WRITE;
+    // SAFETY: ffi
+    let ret = unsafe { libc::mmap(ptr::null_mut(), len, prot, flags, -1, 0) };
+    if ret == libc::MAP_FAILED {
+        return Err(last_error());
+    }
+    Ok(ptr::NonNull::new(ret.cast()).unwrap())
+}
+
+/// Maps `len` bytes aligned to `align`, which is required by transparent huge pages.
+unsafe fn mmap_aligned(len: usize, align: usize) -> io::Result<ptr::NonNull<u8>> {
+    let total = len
+        .checked_add(align)
+        .ok_or_else(|| io::Error::from(io::ErrorKind::OutOfMemory))?;
+    // SAFETY: anonymous mapping
+    let base = unsafe { mmap(total, libc::MAP_PRIVATE | libc::MAP_ANONYMOUS)? };
+    let addr = ptr_to_addr(base.as_ptr());
+    let head = addr.next_multiple_of(align) - addr;
+    let tail = total - head - len;
+    // SAFETY: ffi, trimming the unaligned ends of the mapping
+    unsafe {
+        if head > 0 {
+            libc::munmap(base.as_ptr().cast(), head);
+        }
+        if tail > 0 {
+            libc::munmap(base.as_ptr().add(head + len).cast(), tail);
+        }
+        Ok(ptr::NonNull::new_unchecked(base.as_ptr().add(head)))
+    }
+}
+
+pub(crate) fn page_size() -> usize {
+    // SAFETY: ffi
+    let ret = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    usize::try_from(ret).unwrap_or(4096)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_up_len() {
+        assert_eq!(round_up(1, 4096).unwrap(), 4096);
+        assert_eq!(round_up(4096, 4096).unwrap(), 4096);
+        assert_eq!(round_up(4097, 4096).unwrap(), 8192);
+        assert_eq!(round_up(0, 4096).unwrap(), 0);
+        let err = round_up(usize::MAX, 4096).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::OutOfMemory);
+    }
+
+    #[test]
+    fn aligned_buffer() {
+        let buf = AlignedBuffer::new(100);
+        assert_eq!(buf.len(), 100);
+        assert_eq!(ptr_to_addr(buf.as_ptr()) % page_size(), 0);
+        assert!(buf.as_slice().iter().all(|&b| b == 0));
+
+        let mut buf = AlignedBuffer::with_alignment(3, 64);
+        assert_eq!(ptr_to_addr(buf.as_ptr()) % 64, 0);
+        buf.as_mut_slice().copy_from_slice(b"abc");
+        assert_eq!(buf.as_slice(), b"abc");
+
+        let buf = AlignedBuffer::new(0);
+        assert!(buf.is_empty());
+        assert!(buf.as_slice().is_empty());
+    }
+
+    #[test]
+    fn huge_page_fallback() {
+        let thp_size = HugePageSize::Size2M.bytes();
+        let mut buf = HugePageBuffer::alloc_fallback(5000).unwrap();
+        assert_eq!(buf.len(), 5000);
+        assert_eq!(buf.map_len, thp_size);
+        assert_eq!(ptr_to_addr(buf.as_ptr()) % thp_size, 0);
+        assert_ne!(buf.page_kind(), PageKind::HugeTlb(HugePageSize::Size2M));
+        assert!(buf.as_slice().iter().all(|&b| b == 0));
+        buf.as_mut_slice().fill(1);
+        assert!(buf.as_slice().iter().all(|&b| b == 1));
+
+        let buf = HugePageBuffer::alloc_fallback(thp_size + 1).unwrap();
+        assert_eq!(buf.map_len, 2 * thp_size);
+    }
+
+    #[test]
+    fn huge_page_alloc() {
+        // hugetlbfs is usually unavailable, where it falls back to 2 MiB mappings
+        let buf = HugePageBuffer::alloc(1, HugePageSize::Size1G).unwrap();
+        assert_eq!(buf.len(), 1);
+        match buf.page_kind() {
+            PageKind::HugeTlb(size) => assert_eq!(size, HugePageSize::Size1G),
+            PageKind::TransparentAdvised | PageKind::Normal => {
+                assert_eq!(buf.map_len, HugePageSize::Size2M.bytes());
+            }
+        }
+    }
+
+    #[test]
+    fn thp_mode() {
+        assert!(thp_mode_allows_advice("[always] madvise never\n"));
+        assert!(thp_mode_allows_advice("always [madvise] never\n"));
+        assert!(!thp_mode_allows_advice("always madvise [never]\n"));
+        assert!(!thp_mode_allows_advice(""));
+    }
+}
@@ -1,12 +1,20 @@
 use crate::cq::{self, CompletionQueue};
 use crate::ctx::Context;
-use crate::error::{create_resource, custom_error};
+#[cfg(feature = "tokio")]
+use crate::error::custom_error;
+use crate::error::{create_resource, last_error};
+use crate::utils::set_nonblocking;
 use crate::weakset::WeakSet;
 
 use std::os::raw::c_void;
 use std::os::unix::prelude::{AsRawFd, RawFd};
 use std::{io, ptr, sync};
 
+#[cfg(feature = "tokio")]
+use std::mem::ManuallyDrop;
+#[cfg(feature = "tokio")]
+use std::sync::atomic::{AtomicBool, Ordering};
+
 #[derive(Clone)]
 pub struct CompChannel(sync::Arc<Owner>);
 
@@ -27,12 +35,21 @@ impl CompChannel {
             sync::Arc::new(Owner {
                 cc,
                 cq_ref: sync::Mutex::new(WeakSet::new()),
+                #[cfg(feature = "tokio")]
+                registered: AtomicBool::new(false),
                 _ctx: ctx.clone(),
             })
         };
         Ok(Self(owner))
     }
 
+    /// Sets the channel to non-blocking mode,
+    /// where [`CompChannel::wait_cq_event`] fails with `WouldBlock` if there is no event.
+    #[inline]
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        set_nonblocking(self.as_raw_fd(), nonblocking)
+    }
+
     #[inline]
     pub fn wait_cq_event(&self) -> io::Result<CompletionQueue> {
         let cc = self.ffi_ptr();
@@ -42,7 +59,7 @@ impl CompChannel {
         unsafe {
             let ret = ibverbs_sys::ibv_get_cq_event(cc, &mut cq, &mut cq_context);
             if ret != 0 {
-                return Err(custom_error("failed to get completion event"));
+                return Err(last_error());
             }
             debug_assert_eq!((*cq).cq_context, cq_context);
         }
@@ -73,10 +90,85 @@ impl AsRawFd for CompChannel {
     }
 }
 
+/// A completion channel registered in the tokio reactor
+///
+/// A channel can be registered only once at a time.
+/// It stays in non-blocking mode until the registration is dropped,
+/// so [`CompChannel::wait_cq_event`] on its clones fails with `WouldBlock` meanwhile.
+#[cfg(feature = "tokio")]
+pub struct AsyncCompChannel(ManuallyDrop<tokio::io::unix::AsyncFd<CompChannel>>);
+
+#[cfg(feature = "tokio")]
+impl AsyncCompChannel {
+    /// Sets the channel to non-blocking mode and registers it.
+    ///
+    /// Fails if the channel is already registered.
+    ///
+    /// # Panics
+    /// + if it is not called within a tokio runtime with IO enabled
+    #[inline]
+    pub fn new(cc: CompChannel) -> io::Result<Self> {
+        if cc.0.registered.swap(true, Ordering::AcqRel) {
+            return Err(custom_error("the completion channel is already registered"));
+        }
+        let interest = tokio::io::Interest::READABLE;
+        let ret = cc
+            .set_nonblocking(true)
+            .and_then(|()| tokio::io::unix::AsyncFd::with_interest(cc.clone(), interest));
+        match ret {
+            Ok(fd) => Ok(Self(ManuallyDrop::new(fd))),
+            Err(err) => {
+                unregister(&cc);
+                Err(err)
+            }
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn get_ref(&self) -> &CompChannel {
+        self.0.get_ref()
+    }
+
+    /// Waits for the next completion event.
+    ///
+    /// The event must be acknowledged by [`CompletionQueue::ack_cq_events`].
+    #[inline]
+    pub async fn next_cq_event(&self) -> io::Result<CompletionQueue> {
+        loop {
+            let mut guard = self.0.readable().await?;
+            if let Ok(ret) = guard.try_io(|inner| inner.get_ref().wait_cq_event()) {
+                return ret;
+            }
+        }
+    }
+}
+
+/// Restores the blocking mode and releases the registration.
+#[cfg(feature = "tokio")]
+impl Drop for AsyncCompChannel {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: the field is not used after here
+        let fd = unsafe { ManuallyDrop::take(&mut self.0) };
+        // deregister from the reactor before the channel can be registered again
+        unregister(&fd.into_inner());
+    }
+}
+
+#[cfg(feature = "tokio")]
+fn unregister(cc: &CompChannel) {
+    // the fd is still valid, so it fails only if fcntl is interrupted
+    let _ = cc.set_nonblocking(false);
+    cc.0.registered.store(false, Ordering::Release);
+}
+
 struct Owner {
     cc: ptr::NonNull<ibverbs_sys::ibv_comp_channel>,
 
     cq_ref: sync::Mutex<WeakSet<cq::Owner>>,
+    #[cfg(feature = "tokio")]
+    registered: AtomicBool,
     _ctx: Context,
 }
 
@@ -0,0 +1,161 @@
+use crate::ctx::Context;
+use crate::device::DeviceAttrEx;
+use crate::error::custom_error;
+
+use std::io;
+use std::time::{Duration, SystemTime};
+
+/// Converts hardware clock cycles into durations and wall clock time.
+///
+/// The clock is calibrated against [`SystemTime::now`] once,
+/// so it should be recalibrated periodically to compensate for drift.
+#[derive(Debug, Clone, Copy)]
+pub struct HcaClock {
+    freq_khz: u64,
+    mask: u64,
+    base_cycles: u64,
+    base_time: SystemTime,
+}
+
+impl HcaClock {
+    #[inline]
+    pub fn calibrate(ctx: &Context) -> io::Result<Self> {
+        let attr = DeviceAttrEx::query(ctx)?;
+        let freq_khz = attr.hca_core_clock();
+        if freq_khz == 0 {
+            return Err(custom_error("the device does not report the core clock"));
+        }
+        let mask = match attr.completion_timestamp_mask() {
+            0 => u64::MAX,
+            mask => mask,
+        };
+        let base_cycles = ctx.query_rt_values()?.raw_clock() & mask;
+        let base_time = SystemTime::now();
+        Ok(Self {
+            freq_khz,
+            mask,
+            base_cycles,
+            base_time,
+        })
+    }
+
+    /// Returns the frequency of the core clock in kHz.
+    #[inline]
+    #[must_use]
+    pub fn freq_khz(&self) -> u64 {
+        self.freq_khz
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn cycles_to_duration(&self, cycles: u64) -> Duration {
+        let nanos = u128::from(cycles) * 1_000_000 / u128::from(self.freq_khz);
+        Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+    }
+
+    /// Converts a completion timestamp into wall clock time.
+    ///
+    /// The timestamp may wrap around within [`DeviceAttrEx::completion_timestamp_mask`],
+    /// so it is interpreted as the nearest point to the calibration.
+    ///
+    /// Returns `None` if the time can not be represented by [`SystemTime`].
+    #[inline]
+    #[must_use]
+    pub fn to_system_time(&self, cycles: u64) -> Option<SystemTime> {
+        let ahead = cycles.wrapping_sub(self.base_cycles) & self.mask;
+        if ahead <= self.mask / 2 {
+            self.base_time.checked_add(self.cycles_to_duration(ahead))
+        } else {
+            let behind = self.base_cycles.wrapping_sub(cycles) & self.mask;
+            self.base_time.checked_sub(self.cycles_to_duration(behind))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::UNIX_EPOCH;
+
+    /// A 1 GHz clock, where a cycle is a nanosecond
+    fn clock(mask: u64, base_cycles: u64, base_time: SystemTime) -> HcaClock {
+        HcaClock {
+            freq_khz: 1_000_000,
+            mask,
+            base_cycles,
+            base_time,
+        }
+    }
+
+    #[test]
+    fn cycles_to_duration() {
+        let clock = clock(u64::MAX, 0, UNIX_EPOCH);
+        assert_eq!(clock.cycles_to_duration(0), Duration::ZERO);
+        assert_eq!(clock.cycles_to_duration(1_500), Duration::from_nanos(1_500));
+
+        let clock = HcaClock {
+            freq_khz: 156_250,
+            ..clock
+        };
+        assert_eq!(clock.cycles_to_duration(156_250), Duration::from_millis(1));
+
+        let clock = HcaClock {
+            freq_khz: 1,
+            ..clock
+        };
+        assert_eq!(clock.cycles_to_duration(3), Duration::from_millis(3));
+        let max = Duration::from_nanos(u64::MAX);
+        assert_eq!(clock.cycles_to_duration(u64::MAX), max);
+    }
+
+    #[test]
+    fn ahead_and_behind() {
+        let base = UNIX_EPOCH + Duration::from_secs(1_000);
+        let clock = clock(u64::MAX, 1_000, base);
+        let ns = Duration::from_nanos;
+        assert_eq!(clock.to_system_time(1_000), Some(base));
+        assert_eq!(clock.to_system_time(1_500), Some(base + ns(500)));
+        assert_eq!(clock.to_system_time(400), Some(base - ns(600)));
+    }
+
+    #[test]
+    fn wraparound() {
+        let mask = (1 << 48) - 1;
+        let base = UNIX_EPOCH + Duration::from_secs(1_000);
+        let ns = Duration::from_nanos;
+
+        let clock = clock(mask, mask - 99, base);
+        assert_eq!(clock.to_system_time(100), Some(base + ns(200)));
+        // the bits above the mask are ignored
+        assert_eq!(clock.to_system_time(100 | (1 << 48)), Some(base + ns(200)));
+
+        let clock = HcaClock {
+            base_cycles: 50,
+            ..clock
+        };
+        assert_eq!(clock.to_system_time(mask - 49), Some(base - ns(100)));
+        // half of the range ahead is the farthest future point
+        assert_eq!(
+            clock.to_system_time(50 + mask / 2),
+            Some(base + ns(mask / 2))
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        let limit = Duration::from_secs(i64::MAX.unsigned_abs() - 10);
+
+        let clock = clock(u64::MAX, 0, UNIX_EPOCH + limit);
+        assert!(clock.to_system_time(5_000_000_000).is_some());
+        assert_eq!(clock.to_system_time(20_000_000_000), None);
+
+        let clock = HcaClock {
+            base_time: UNIX_EPOCH - limit,
+            base_cycles: 20_000_000_000,
+            ..clock
+        };
+        assert!(clock.to_system_time(15_000_000_000).is_some());
+        assert_eq!(clock.to_system_time(0), None);
+    }
+}
@@ -0,0 +1,329 @@
+//! Connects RC queue pairs by exchanging [`ConnectionInfo`] over TCP.
+//!
+//! Each side moves its queue pair to `Initialize`, sends its endpoint,
+//! moves to `ReadyToReceive` and `ReadyToSend` with the peer's endpoint
+//! and finally waits for the peer to finish, so that no message is sent
+//! before the receiving queue pair is ready.
+
+use crate::ctx::Context;
+use crate::device::{Gid, GidEntry, LinkLayer, Mtu, PortAttr};
+use crate::endpoint::{ConnectionInfo, RemoteBuffer};
+use crate::error::custom_error;
+use crate::mr::AccessFlags;
+use crate::qp::{InitAttr, PathAttr, QueuePair, RtsAttr};
+
+use std::hash::{BuildHasher, RandomState};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+use numeric_cast::NumericCast;
+
+/// A connected queue pair
+pub struct Connection {
+    qp: QueuePair,
+    remote: ConnectionInfo,
+    stream: TcpStream,
+}
+
+impl Connection {
+    #[inline]
+    #[must_use]
+    pub fn qp(&self) -> &QueuePair {
+        &self.qp
+    }
+
+    /// Returns the endpoint of the peer.
+    #[inline]
+    #[must_use]
+    pub fn remote(&self) -> &ConnectionInfo {
+        &self.remote
+    }
+
+    /// Returns the memory regions advertised by the peer.
+    #[inline]
+    #[must_use]
+    pub fn remote_buffers(&self) -> &[RemoteBuffer] {
+        &self.remote.buffers
+    }
+
+    /// Returns the TCP stream which is still connected to the peer.
+    #[inline]
+    #[must_use]
+    pub fn stream(&self) -> &TcpStream {
+        &self.stream
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn into_parts(self) -> (QueuePair, ConnectionInfo, TcpStream) {
+        (self.qp, self.remote, self.stream)
+    }
+}
+
+pub struct ConnectOptions {
+    port_num: u8,
+    gid_index: Option<u8>,
+    mtu: Option<Mtu>,
+    psn: u32,
+    pkey_index: u16,
+    access_flags: AccessFlags,
+    service_level: u8,
+    hop_limit: u8,
+    timeout: u8,
+    retry_cnt: u8,
+    rnr_retry: u8,
+    max_rd_atomic: u8,
+    max_dest_rd_atomic: u8,
+    min_rnr_timer: u8,
+    buffers: Vec<RemoteBuffer>,
+}
+
+impl Default for ConnectOptions {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            port_num: 1,
+            gid_index: None,
+            mtu: None,
+            psn: random_psn(),
+            pkey_index: 0,
+            access_flags: AccessFlags::LOCAL_WRITE
+                | AccessFlags::REMOTE_WRITE
+                | AccessFlags::REMOTE_READ
+                | AccessFlags::REMOTE_ATOMIC,
+            service_level: 0,
+            hop_limit: 64,
+            timeout: 14,
+            retry_cnt: 7,
+            rnr_retry: 7,
+            max_rd_atomic: 1,
+            max_dest_rd_atomic: 1,
+            min_rnr_timer: 12,
+            buffers: Vec::new(),
+        }
+    }
+}
+
+impl ConnectOptions {
+    #[inline]
+    pub fn port_num(&mut self, port_num: u8) -> &mut Self {
+        self.port_num = port_num;
+        self
+    }
+
+    /// Selects the local GID, which is required by RoCE.
+    #[inline]
+    pub fn gid_index(&mut self, gid_index: u8) -> &mut Self {
+        self.gid_index = Some(gid_index);
+        self
+    }
+
+    /// Limits the path MTU, which defaults to the active MTU of the port.
+    #[inline]
+    pub fn mtu(&mut self, mtu: Mtu) -> &mut Self {
+        self.mtu = Some(mtu);
+        self
+    }
+
+    /// Sets the initial packet sequence number of the send queue,
+    /// which is random by default and truncated to 24 bits.
+    #[inline]
+    pub fn psn(&mut self, psn: u32) -> &mut Self {
+        self.psn = psn & PSN_MASK;
+        self
+    }
+
+    #[inline]
+    pub fn pkey_index(&mut self, pkey_index: u16) -> &mut Self {
+        self.pkey_index = pkey_index;
+        self
+    }
+
+    #[inline]
+    pub fn access_flags(&mut self, access_flags: AccessFlags) -> &mut Self {
+        self.access_flags = access_flags;
+        self
+    }
+
+    #[inline]
+    pub fn service_level(&mut self, service_level: u8) -> &mut Self {
+        self.service_level = service_level;
+        self
+    }
+
+    #[inline]
+    pub fn hop_limit(&mut self, hop_limit: u8) -> &mut Self {
+        self.hop_limit = hop_limit;
+        self
+    }
+
+    #[inline]
+    pub fn timeout(&mut self, timeout: u8) -> &mut Self {
+        self.timeout = timeout;
+        self
+    }
+
+    #[inline]
+    pub fn retry_cnt(&mut self, retry_cnt: u8) -> &mut Self {
+        self.retry_cnt = retry_cnt;
+        self
+    }
+
+    #[inline]
+    pub fn rnr_retry(&mut self, rnr_retry: u8) -> &mut Self {
+        self.rnr_retry = rnr_retry;
+        self
+    }
+
+    #[inline]
+    pub fn max_rd_atomic(&mut self, max_rd_atomic: u8) -> &mut Self {
+        self.max_rd_atomic = max_rd_atomic;
+        self
+    }
+
+    #[inline]
+    pub fn max_dest_rd_atomic(&mut self, max_dest_rd_atomic: u8) -> &mut Self {
+        self.max_dest_rd_atomic = max_dest_rd_atomic;
+        self
+    }
+
+    #[inline]
+    pub fn min_rnr_timer(&mut self, min_rnr_timer: u8) -> &mut Self {
+        self.min_rnr_timer = min_rnr_timer;
+        self
+    }
+
+    /// Advertises a memory region to the peer.
+    #[inline]
+    pub fn buffer(&mut self, buffer: RemoteBuffer) -> &mut Self {
+        self.buffers.push(buffer);
+        self
+    }
+}
+
+const PSN_MASK: u32 = 0x00ff_ffff;
+
+/// Returns a random 24-bit packet sequence number.
+fn random_psn() -> u32 {
+    let random = RandomState::new().hash_one(0_u8);
+    (random & u64::from(PSN_MASK)).numeric_cast()
+}
+
+#[inline]
+pub fn listen(addr: impl ToSocketAddrs) -> io::Result<TcpListener> {
+    TcpListener::bind(addr)
+}
+
+/// Accepts a peer and connects `qp` to it.
+#[inline]
+pub fn accept(
+    listener: &TcpListener,
+    ctx: &Context,
+    qp: &QueuePair,
+    options: &ConnectOptions,
+) -> io::Result<Connection> {
+    let (stream, _) = listener.accept()?;
+    handshake(stream, ctx, qp, options)
+}
+
+/// Connects `qp` to a peer which is waiting in [`accept`].
+#[inline]
+pub fn connect(
+    addr: impl ToSocketAddrs,
+    ctx: &Context,
+    qp: &QueuePair,
+    options: &ConnectOptions,
+) -> io::Result<Connection> {
+    let stream = TcpStream::connect(addr)?;
+    handshake(stream, ctx, qp, options)
+}
+
+fn handshake(
+    mut stream: TcpStream,
+    ctx: &Context,
+    qp: &QueuePair,
+    options: &ConnectOptions,
+) -> io::Result<Connection> {
+    stream.set_nodelay(true)?;
+
+    let port_attr = PortAttr::query(ctx, options.port_num)?;
+    let gid = match options.gid_index {
+        Some(gid_index) => GidEntry::query(ctx, options.port_num.into(), gid_index.into())?.gid(),
+        None if port_attr.link_layer() == LinkLayer::Ethernet => {
+            return Err(custom_error("a gid index is required by RoCE"));
+        }
+        None => Gid::from_bytes([0; 16]),
+    };
+    let mtu = match options.mtu {
+        Some(mtu) => mtu.min(port_attr.active_mtu()),
+        None => port_attr.active_mtu(),
+    };
+
+    let local = ConnectionInfo {
+        qp_num: qp.qp_num(),
+        psn: options.psn,
+        lid: port_attr.lid(),
+        gid,
+        port_num: options.port_num,
+        mtu,
+        buffers: options.buffers.clone(),
+    };
+
+    qp.to_init(&InitAttr {
+        port_num: options.port_num,
+        pkey_index: options.pkey_index,
+        access_flags: Some(options.access_flags),
+        qkey: None,
+    })?;
+
+    let remote = exchange(&mut stream, &local)?;
+
+    let path = PathAttr {
+        port_num: options.port_num,
+        mtu,
+        sgid_index: options.gid_index.unwrap_or(0),
+        service_level: options.service_level,
+        hop_limit: options.hop_limit,
+        traffic_class: 0,
+        flow_label: 0,
+    };
+    qp.to_rtr(&remote.rtr_attr(path, options.max_dest_rd_atomic, options.min_rnr_timer))?;
+
+    qp.to_rts(&RtsAttr {
+        sq_psn: options.psn,
+        timeout: Some(options.timeout),
+        retry_cnt: Some(options.retry_cnt),
+        rnr_retry: Some(options.rnr_retry),
+        max_rd_atomic: Some(options.max_rd_atomic),
+    })?;
+
+    // wait until both sides are ready
+    stream.write_all(&[0])?;
+    stream.read_exact(&mut [0])?;
+
+    Ok(Connection {
+        qp: qp.clone(),
+        remote,
+        stream,
+    })
+}
+
+/// Sends the local endpoint and receives the remote one, both prefixed by their length.
+fn exchange(stream: &mut TcpStream, local: &ConnectionInfo) -> io::Result<ConnectionInfo> {
+    let len: u32 = local.encoded_len().numeric_cast();
+    let mut buf = Vec::with_capacity(4 + local.encoded_len());
+    buf.extend_from_slice(&len.to_be_bytes());
+    local.encode(&mut buf);
+    stream.write_all(&buf)?;
+
+    let mut len = [0; 4];
+    stream.read_exact(&mut len)?;
+    let len: usize = u32::from_be_bytes(len).numeric_cast();
+    let max_len = ConnectionInfo::HEADER_LEN + ConnectionInfo::BUFFER_LEN * usize::from(u16::MAX);
+    if len > max_len {
+        return Err(custom_error("connection info is too long"));
+    }
+    let mut buf = vec![0; len];
+    stream.read_exact(&mut buf)?;
+    ConnectionInfo::decode(&buf)
+}
@@ -1,14 +1,19 @@
 use crate::ctx::Context;
-use crate::error::{create_resource, from_errno};
+use crate::device::DeviceAttr;
+use crate::error::{create_resource, custom_error, from_errno};
 use crate::utils::ptr_as_mut;
 
+#[cfg(feature = "tokio")]
+use crate::cc::AsyncCompChannel;
 use crate::cc::CompChannel;
-use crate::wc::WorkCompletion;
+use crate::wc::{Opcode, WorkCompletion, WorkCompletionFlags};
 use std::{
     ffi, io, mem, os, ptr, slice,
     sync::{self, atomic},
 };
 
+use numeric_cast::NumericCast;
+
 #[derive(Clone)]
 pub struct CompletionQueue(sync::Arc<Owner>);
 
@@ -25,6 +30,7 @@ impl CompletionQueue {
 
             let mut cq_attr: ibverbs_sys::ibv_cq_init_attr_ex = mem::zeroed();
             cq_attr.cqe = options.cqe as u32;
+            cq_attr.wc_flags = u64::from(options.wc_flags.bits());
 
             if let Some(ref cc) = options.channel {
                 cq_attr.channel = cc.ffi_ptr();
@@ -38,12 +44,16 @@ impl CompletionQueue {
             sync::Arc::new(Owner {
                 cq,
                 user_data: options.user_data,
+                wc_flags: options.wc_flags,
                 comp_events_completed: sync::atomic::AtomicU32::new(0),
-                _ctx: ctx.clone(),
+                poll_lock: sync::Mutex::new(()),
+                ctx: ctx.clone(),
                 cc: options.channel,
             })
         };
 
+        ctx.add_cq_ref(sync::Arc::downgrade(&owner));
+
         if let Some(ref cc) = owner.cc {
             cc.add_cq_ref(sync::Arc::downgrade(&owner));
         }
@@ -60,15 +70,31 @@ impl CompletionQueue {
 
     /// # Panics
     /// + if the completion queue has been destroyed
+    /// + if the context is not set yet
     ///
     /// # SAFETY
     /// 1. `cq_context` must come from the pointee of `CompletionQueue::ffi_ptr`
     /// 2. there must be at least one weak reference to the completion queue owner
     pub(crate) unsafe fn from_cq_context(cq_context: *mut ffi::c_void) -> Self {
+        // SAFETY: guaranteed by caller
+        unsafe { Self::upgrade_cq_context(cq_context) }
+            .expect("the completion queue has been destroyed")
+    }
+
+    /// Returns `None` if the completion queue is being destroyed,
+    /// or if the context is still null because an event arrives before `create` sets it.
+    ///
+    /// # SAFETY
+    /// 1. `cq_context` must come from the pointee of `CompletionQueue::ffi_ptr`
+    /// 2. there must be at least one weak reference to the completion queue owner
+    pub(crate) unsafe fn upgrade_cq_context(cq_context: *mut ffi::c_void) -> Option<Self> {
+        if cq_context.is_null() {
+            return None;
+        }
         let owner_ptr: *const Owner = cq_context.cast();
-        let weak = mem::ManuallyDrop::new(sync::Weak::from_raw(owner_ptr));
-        let owner = sync::Weak::upgrade(&weak).expect("the completion queue has been destroyed");
-        Self(owner)
+        // SAFETY: guaranteed by caller
+        let weak = mem::ManuallyDrop::new(unsafe { sync::Weak::from_raw(owner_ptr) });
+        sync::Weak::upgrade(&weak).map(Self)
     }
 
     #[inline]
@@ -107,6 +133,11 @@ impl CompletionQueue {
             .fetch_add(cnt, atomic::Ordering::Relaxed);
     }
 
+    /// Polls completions into `buf`.
+    ///
+    /// It must not be mixed with [`CompletionQueue::start_poll`]:
+    /// calling it while a [`PollSession`] of the completion queue is alive
+    /// is not allowed and may deadlock in the provider.
     #[inline]
     pub fn poll<'wc>(
         &self,
@@ -126,15 +157,273 @@ impl CompletionQueue {
             Ok(slice::from_raw_parts_mut(data, len))
         }
     }
+
+    /// Returns a stream which waits for completions on the channel of the completion queue.
+    ///
+    /// The channel should not be shared with other completion queues,
+    /// because their events would be consumed by the stream.
+    ///
+    /// Fails if the channel is already registered by another stream
+    /// or [`AsyncCompChannel`], until that one is dropped.
+    ///
+    /// # Panics
+    /// + if it is not called within a tokio runtime with IO enabled
+    #[cfg(feature = "tokio")]
+    #[inline]
+    pub fn stream(&self, capacity: usize) -> io::Result<CompletionStream> {
+        let cc = (self.0.cc.clone())
+            .ok_or_else(|| custom_error("the completion queue has no channel"))?;
+        Ok(CompletionStream {
+            cq: self.clone(),
+            cc: AsyncCompChannel::new(cc)?,
+            buf: vec![mem::MaybeUninit::uninit(); capacity.max(1)],
+            pos: 0,
+            len: 0,
+            armed: false,
+        })
+    }
+
+    /// Starts polling with the extended interface.
+    ///
+    /// Returns `None` if the completion queue is empty.
+    /// Otherwise the returned session is positioned at the first completion.
+    ///
+    /// The session holds the session lock of the completion queue until it is dropped,
+    /// so starting another session from another clone or thread blocks meanwhile.
+    /// Starting another session on the same thread while the session is alive
+    /// may deadlock or panic, and [`CompletionQueue::poll`] must not be called meanwhile.
+    #[inline]
+    pub fn start_poll(&self) -> io::Result<Option<PollSession<'_>>> {
+        let guard = self.0.lock_poll();
+        let cq = self.ffi_ptr();
+        // SAFETY: ffi
+        let ret = unsafe {
+            let mut attr: ibverbs_sys::ibv_poll_cq_attr = mem::zeroed();
+            ibverbs_sys::ibv_start_poll(cq, &mut attr)
+        };
+        match ret {
+            0 => Ok(Some(PollSession {
+                cq: self,
+                _guard: guard,
+            })),
+            libc::ENOENT => Ok(None),
+            _ => Err(from_errno(ret)),
+        }
+    }
+}
+
+/// An asynchronous stream of completions
+#[cfg(feature = "tokio")]
+pub struct CompletionStream {
+    cq: CompletionQueue,
+    cc: AsyncCompChannel,
+    buf: Vec<mem::MaybeUninit<WorkCompletion>>,
+    pos: usize,
+    len: usize,
+    armed: bool,
+}
+
+#[cfg(feature = "tokio")]
+impl CompletionStream {
+    #[inline]
+    #[must_use]
+    pub fn cq(&self) -> &CompletionQueue {
+        &self.cq
+    }
+
+    /// Waits for the next completion.
+    ///
+    /// The completion queue is drained in batches of the stream capacity.
+    /// When it is empty, the notification is re-armed and the stream waits
+    /// for a completion event, which is acknowledged before polling again.
+    #[inline]
+    pub async fn next(&mut self) -> io::Result<WorkCompletion> {
+        loop {
+            if self.pos < self.len {
+                // SAFETY: initialized by `CompletionQueue::poll`
+                let wc = unsafe { self.buf[self.pos].assume_init() };
+                self.pos += 1;
+                return Ok(wc);
+            }
+
+            self.pos = 0;
+            self.len = self.cq.poll(&mut self.buf)?.len();
+            if self.len > 0 {
+                continue;
+            }
+
+            if !self.armed {
+                // poll again after arming to catch the completions which arrive in between
+                self.cq.req_notify_all()?;
+                self.armed = true;
+                continue;
+            }
+
+            let cq = self.cc.next_cq_event().await?;
+            cq.ack_cq_events(1);
+            self.armed = false;
+        }
+    }
+}
+
+/// A polling session which reads completions in place.
+///
+/// The optional readers return `None` if the field is not enabled by
+/// [`CompletionQueueOptions::wc_flags`].
+pub struct PollSession<'cq> {
+    cq: &'cq CompletionQueue,
+    _guard: sync::MutexGuard<'cq, ()>,
+}
+
+macro_rules! read_field {
+    ($field: ident, $flag: ident, $ty: ty, $read: ident) => {
+        #[inline]
+        #[must_use]
+        pub fn $field(&self) -> Option<$ty> {
+            let cq = self.ffi_ptr();
+            self.has(WcFlags::$flag).then(|| {
+                // SAFETY: ffi, the field is enabled
+                unsafe { ibverbs_sys::$read(cq) }
+            })
+        }
+    };
+}
+
+impl PollSession<'_> {
+    fn ffi_ptr(&self) -> *mut ibverbs_sys::ibv_cq_ex {
+        self.cq.ffi_ptr()
+    }
+
+    fn has(&self, flag: WcFlags) -> bool {
+        self.cq.0.wc_flags.contains(flag)
+    }
+
+    /// Advances to the next completion.
+    ///
+    /// Returns `false` if there are no more completions.
+    #[allow(clippy::should_implement_trait)]
+    #[inline]
+    pub fn next(&mut self) -> io::Result<bool> {
+        // SAFETY: ffi
+        let ret = unsafe { ibverbs_sys::ibv_next_poll(self.ffi_ptr()) };
+        match ret {
+            0 => Ok(true),
+            libc::ENOENT => Ok(false),
+            _ => Err(from_errno(ret)),
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn wr_id(&self) -> u64 {
+        // SAFETY: reading the current completion
+        unsafe { (*self.ffi_ptr()).wr_id }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn status(&self) -> u32 {
+        // SAFETY: reading the current completion
+        unsafe { (*self.ffi_ptr()).status }
+    }
+
+    /// Returns the opcode of the current completion.
+    ///
+    /// Returns `None` for unknown opcodes, such as vendor opcodes.
+    /// The opcode is undefined if the completion failed.
+    #[inline]
+    #[must_use]
+    pub fn opcode(&self) -> Option<Opcode> {
+        // SAFETY: ffi
+        let opcode = unsafe { ibverbs_sys::ibv_wc_read_opcode(self.ffi_ptr()) };
+        Opcode::try_from(opcode).ok()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn vendor_err(&self) -> u32 {
+        // SAFETY: ffi
+        unsafe { ibverbs_sys::ibv_wc_read_vendor_err(self.ffi_ptr()) }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn wc_flags(&self) -> WorkCompletionFlags {
+        // SAFETY: ffi
+        let flags = unsafe { ibverbs_sys::ibv_wc_read_wc_flags(self.ffi_ptr()) };
+        WorkCompletionFlags::from_bits_truncate(flags)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn imm_data(&self) -> Option<u32> {
+        if !self.wc_flags().contains(WorkCompletionFlags::WITH_IMM) {
+            return None;
+        }
+        let cq = self.ffi_ptr();
+        self.has(WcFlags::IMM).then(|| {
+            // SAFETY: ffi, the field is enabled
+            unsafe { ibverbs_sys::ibv_wc_read_imm_data(cq) }
+        })
+    }
+
+    read_field!(byte_len, BYTE_LEN, u32, ibv_wc_read_byte_len);
+    read_field!(qp_num, QP_NUM, u32, ibv_wc_read_qp_num);
+    read_field!(src_qp, SRC_QP, u32, ibv_wc_read_src_qp);
+    read_field!(slid, SLID, u32, ibv_wc_read_slid);
+    read_field!(sl, SL, u8, ibv_wc_read_sl);
+    read_field!(
+        dlid_path_bits,
+        DLID_PATH_BITS,
+        u8,
+        ibv_wc_read_dlid_path_bits
+    );
+    read_field!(cvlan, CVLAN, u16, ibv_wc_read_cvlan);
+    read_field!(flow_tag, FLOW_TAG, u32, ibv_wc_read_flow_tag);
+
+    /// Returns the completion timestamp in hardware clock cycles.
+    ///
+    /// See [`HcaClock`](crate::clock::HcaClock) for the conversion.
+    #[inline]
+    #[must_use]
+    pub fn completion_ts(&self) -> Option<u64> {
+        let cq = self.ffi_ptr();
+        self.has(WcFlags::COMPLETION_TIMESTAMP).then(|| {
+            // SAFETY: ffi, the field is enabled
+            unsafe { ibverbs_sys::ibv_wc_read_completion_ts(cq) }
+        })
+    }
+
+    /// Returns the completion timestamp in nanoseconds of the device wall clock.
+    #[inline]
+    #[must_use]
+    pub fn completion_wallclock_ns(&self) -> Option<u64> {
+        let cq = self.ffi_ptr();
+        self.has(WcFlags::COMPLETION_TIMESTAMP_WALLCLOCK).then(|| {
+            // SAFETY: ffi, the field is enabled
+            unsafe { ibverbs_sys::ibv_wc_read_completion_wallclock_ns(cq) }
+        })
+    }
+}
+
+impl Drop for PollSession<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: ffi
+        unsafe { ibverbs_sys::ibv_end_poll(self.ffi_ptr()) }
+    }
 }
 
 pub(crate) struct Owner {
     cq: ptr::NonNull<ibverbs_sys::ibv_cq_ex>,
     user_data: usize,
+    wc_flags: WcFlags,
     comp_events_completed: atomic::AtomicU32,
+    /// Serializes the poll sessions
+    poll_lock: sync::Mutex<()>,
 
     cc: Option<CompChannel>,
-    _ctx: Context,
+    ctx: Context,
 }
 
 /// SAFETY: owned type
@@ -146,6 +435,13 @@ impl Owner {
     pub(crate) fn ffi_ptr(&self) -> *mut ibverbs_sys::ibv_cq_ex {
         self.cq.as_ptr()
     }
+
+    /// The lock protects no data, so a poisoned lock is still usable.
+    fn lock_poll(&self) -> sync::MutexGuard<'_, ()> {
+        self.poll_lock
+            .lock()
+            .unwrap_or_else(sync::PoisonError::into_inner)
+    }
 }
 
 impl Drop for Owner {
@@ -166,6 +462,8 @@ impl Drop for Owner {
             let ret = ibverbs_sys::ibv_destroy_cq(cq);
             assert_eq!(ret, 0);
         };
+        // the owner stays reachable until the affiliated async events are acknowledged
+        assert!(self.ctx.del_cq_ref(self));
     }
 }
 
@@ -173,6 +471,7 @@ impl Drop for Owner {
 pub struct CompletionQueueOptions {
     cqe: usize,
     user_data: usize,
+    wc_flags: WcFlags,
     channel: Option<CompChannel>,
 }
 
@@ -182,6 +481,12 @@ impl CompletionQueueOptions {
         self.cqe = cqe;
         self
     }
+    /// Clamps the number of entries to the device limit.
+    #[inline]
+    pub fn clamp(&mut self, attr: &DeviceAttr) -> &mut Self {
+        self.cqe = self.cqe.min(attr.max_cqe().numeric_cast());
+        self
+    }
     #[inline]
     pub fn user_data(&mut self, user_data: usize) -> &mut Self {
         self.user_data = user_data;
@@ -192,4 +497,58 @@ impl CompletionQueueOptions {
         self.channel = Some(cc.clone());
         self
     }
+    /// Selects the fields which can be read by [`PollSession`].
+    #[inline]
+    pub fn wc_flags(&mut self, wc_flags: WcFlags) -> &mut Self {
+        self.wc_flags = wc_flags;
+        self
+    }
+}
+
+bitflags::bitflags! {
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct WcFlags: u32 {
+        const BYTE_LEN          = ibverbs_sys::IBV_WC_EX_WITH_BYTE_LEN;
+        const IMM               = ibverbs_sys::IBV_WC_EX_WITH_IMM;
+        const QP_NUM            = ibverbs_sys::IBV_WC_EX_WITH_QP_NUM;
+        const SRC_QP            = ibverbs_sys::IBV_WC_EX_WITH_SRC_QP;
+        const SLID              = ibverbs_sys::IBV_WC_EX_WITH_SLID;
+        const SL                = ibverbs_sys::IBV_WC_EX_WITH_SL;
+        const DLID_PATH_BITS    = ibverbs_sys::IBV_WC_EX_WITH_DLID_PATH_BITS;
+        const CVLAN             = ibverbs_sys::IBV_WC_EX_WITH_CVLAN;
+        const FLOW_TAG          = ibverbs_sys::IBV_WC_EX_WITH_FLOW_TAG;
+        const COMPLETION_TIMESTAMP = ibverbs_sys::IBV_WC_EX_WITH_COMPLETION_TIMESTAMP;
+        const COMPLETION_TIMESTAMP_WALLCLOCK =
+            ibverbs_sys::IBV_WC_EX_WITH_COMPLETION_TIMESTAMP_WALLCLOCK;
+
+        /// The fields of a legacy work completion.
+        const STANDARD = Self::BYTE_LEN.bits()
+            | Self::IMM.bits()
+            | Self::QP_NUM.bits()
+            | Self::SRC_QP.bits()
+            | Self::SLID.bits()
+            | Self::SL.bits()
+            | Self::DLID_PATH_BITS.bits();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamp_cqe() {
+        let attr = ibverbs_sys::ibv_device_attr {
+            max_cqe: 4096,
+            ..Default::default()
+        };
+        let attr = DeviceAttr::from_ctype_ref(&attr);
+
+        let mut options = CompletionQueueOptions::default();
+        options.cqe(1 << 20).clamp(attr);
+        assert_eq!(options.cqe, 4096);
+
+        options.cqe(64).clamp(attr);
+        assert_eq!(options.cqe, 64);
+    }
 }
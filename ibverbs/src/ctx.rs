@@ -1,7 +1,15 @@
-use crate::device::Device;
-use crate::error::create_resource;
+use crate::cq::{self, CompletionQueue};
+use crate::device::{Device, DeviceAttr, GidEntry, PortAttr};
+use crate::error::{create_resource, custom_error, from_errno, last_error};
+use crate::qp::{self, QueuePair};
+use crate::srq::{self, SharedReceiveQueue};
+use crate::utils::set_nonblocking;
+use crate::weakset::WeakSet;
 
-use std::{io, ptr, sync};
+use std::os::unix::prelude::{AsRawFd, RawFd};
+use std::{io, mem, net, ops, ptr, sync};
+
+use numeric_cast::NumericCast;
 
 #[derive(Clone)]
 pub struct Context(sync::Arc<Owner>);
@@ -19,14 +27,323 @@ impl Context {
                 || ibverbs_sys::ibv_open_device(device.ffi_ptr()),
                 || "failed to open device",
             )?;
-            sync::Arc::new(Owner { ctx })
+            sync::Arc::new(Owner {
+                ctx,
+                cq_ref: sync::Mutex::new(WeakSet::new()),
+                qp_ref: sync::Mutex::new(WeakSet::new()),
+                srq_ref: sync::Mutex::new(WeakSet::new()),
+            })
         };
         Ok(Self(owner))
     }
+
+    #[inline]
+    pub fn query_rt_values(&self) -> io::Result<RtValues> {
+        // SAFETY: ffi
+        unsafe {
+            let mut values: ibverbs_sys::ibv_values_ex = mem::zeroed();
+            values.comp_mask = ibverbs_sys::IBV_VALUES_MASK_RAW_CLOCK;
+            let ret = ibverbs_sys::ibv_query_rt_values_ex(self.ffi_ptr(), &mut values);
+            if ret != 0 {
+                return Err(from_errno(ret));
+            }
+            if values.comp_mask & ibverbs_sys::IBV_VALUES_MASK_RAW_CLOCK == 0 {
+                return Err(custom_error("the device does not report the raw clock"));
+            }
+            Ok(RtValues(values))
+        }
+    }
+
+    /// Returns an iterator which queries the ports from 1 to the number of physical ports.
+    #[inline]
+    pub fn ports(&self) -> io::Result<Ports<'_>> {
+        let attr = DeviceAttr::query(self)?;
+        Ok(Ports {
+            ctx: self,
+            port_nums: 1..=attr.phys_port_cnt(),
+        })
+    }
+
+    /// Returns the valid entries of the GID table of the port `port_num`.
+    #[inline]
+    pub fn gid_table(&self, port_num: u8) -> io::Result<Vec<GidEntry>> {
+        GidEntry::query_table(self, port_num)
+    }
+
+    /// Finds the RoCE v2 GID of the port `port_num` whose address is `ip`.
+    ///
+    /// Empty and link-local entries are skipped.
+    #[inline]
+    pub fn find_roce_v2_gid_by_ip(
+        &self,
+        port_num: u8,
+        ip: net::IpAddr,
+    ) -> io::Result<Option<GidEntry>> {
+        let entries = self.gid_table(port_num)?;
+        Ok(GidEntry::select_roce_v2_by_ip(&entries, ip))
+    }
+
+    /// Finds the first RoCE v2 GID of the port `port_num`
+    /// which is associated with the network interface `ifname`.
+    ///
+    /// Empty and link-local entries are skipped.
+    #[inline]
+    pub fn find_roce_v2_gid_by_ifname(
+        &self,
+        port_num: u8,
+        ifname: &str,
+    ) -> io::Result<Option<GidEntry>> {
+        let entries = self.gid_table(port_num)?;
+        GidEntry::select_roce_v2_by_ifname(&entries, ifname)
+    }
+}
+
+/// An iterator over the ports of a device, created by [`Context::ports`]
+pub struct Ports<'a> {
+    ctx: &'a Context,
+    port_nums: ops::RangeInclusive<u8>,
+}
+
+impl Iterator for Ports<'_> {
+    type Item = io::Result<(u8, PortAttr)>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let port_num = self.port_nums.next()?;
+        Some(PortAttr::query(self.ctx, port_num).map(|attr| (port_num, attr)))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.port_nums.size_hint()
+    }
+}
+
+impl Context {
+    /// Waits for the next asynchronous event.
+    ///
+    /// The events of destroyed resources are skipped.
+    /// In non-blocking mode, it fails with `WouldBlock` if there is no event.
+    #[inline]
+    pub fn get_async_event(&self) -> io::Result<AsyncEvent> {
+        loop {
+            // SAFETY: ffi
+            unsafe {
+                let mut event: ibverbs_sys::ibv_async_event = mem::zeroed();
+                let ret = ibverbs_sys::ibv_get_async_event(self.ffi_ptr(), &mut event);
+                if ret != 0 {
+                    return Err(last_error());
+                }
+                let ans = AsyncEvent::from_raw(&event);
+                ibverbs_sys::ibv_ack_async_event(&mut event);
+                if let Some(ans) = ans {
+                    return Ok(ans);
+                }
+            }
+        }
+    }
+
+    /// Sets the asynchronous event file to non-blocking mode,
+    /// where [`Context::get_async_event`] fails with `WouldBlock` if there is no event.
+    #[inline]
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        set_nonblocking(self.as_raw_fd(), nonblocking)
+    }
+
+    pub(crate) fn add_cq_ref(&self, cq: sync::Weak<cq::Owner>) {
+        let mut guard = self.0.cq_ref.lock().unwrap();
+        guard.insert(cq);
+    }
+
+    pub(crate) fn del_cq_ref(&self, cq: &cq::Owner) -> bool {
+        let mut guard = self.0.cq_ref.lock().unwrap();
+        guard.remove(cq)
+    }
+
+    pub(crate) fn add_qp_ref(&self, qp: sync::Weak<qp::Owner>) {
+        let mut guard = self.0.qp_ref.lock().unwrap();
+        guard.insert(qp);
+    }
+
+    pub(crate) fn del_qp_ref(&self, qp: &qp::Owner) -> bool {
+        let mut guard = self.0.qp_ref.lock().unwrap();
+        guard.remove(qp)
+    }
+
+    pub(crate) fn add_srq_ref(&self, srq: sync::Weak<srq::Owner>) {
+        let mut guard = self.0.srq_ref.lock().unwrap();
+        guard.insert(srq);
+    }
+
+    pub(crate) fn del_srq_ref(&self, srq: &srq::Owner) -> bool {
+        let mut guard = self.0.srq_ref.lock().unwrap();
+        guard.remove(srq)
+    }
+}
+
+/// The asynchronous event file of the context
+impl AsRawFd for Context {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        let ctx = self.ffi_ptr();
+        // SAFETY: reading a immutable field of a concurrent ffi type
+        unsafe { (*ctx).async_fd }
+    }
+}
+
+/// A context registered in the tokio reactor for asynchronous events
+#[cfg(feature = "tokio")]
+pub struct AsyncEventStream(tokio::io::unix::AsyncFd<Context>);
+
+#[cfg(feature = "tokio")]
+impl AsyncEventStream {
+    /// Sets the asynchronous event file to non-blocking mode and registers it.
+    ///
+    /// # Panics
+    /// + if it is not called within a tokio runtime with IO enabled
+    #[inline]
+    pub fn new(ctx: Context) -> io::Result<Self> {
+        ctx.set_nonblocking(true)?;
+        let interest = tokio::io::Interest::READABLE;
+        Ok(Self(tokio::io::unix::AsyncFd::with_interest(
+            ctx, interest,
+        )?))
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn get_ref(&self) -> &Context {
+        self.0.get_ref()
+    }
+
+    /// Waits for the next asynchronous event.
+    #[inline]
+    pub async fn next_event(&self) -> io::Result<AsyncEvent> {
+        loop {
+            let mut guard = self.0.readable().await?;
+            if let Ok(ret) = guard.try_io(|inner| inner.get_ref().get_async_event()) {
+                return ret;
+            }
+        }
+    }
+}
+
+/// An asynchronous event of a device
+pub enum AsyncEvent {
+    /// An error occurred on the completion queue, which is usually an overrun
+    CqError(CompletionQueue),
+    /// An error occurred on the queue pair, which is transitioned to the error state
+    QpFatal(QueuePair),
+    /// The queue pair received a request which violates the transport
+    QpRequestError(QueuePair),
+    /// The queue pair received a request which violates the access rights
+    QpAccessError(QueuePair),
+    /// The first packet was received on the queue pair in the RTR state
+    CommEstablished(QueuePair),
+    /// The send queue of the queue pair in the SQD state has been drained
+    SqDrained(QueuePair),
+    /// The queue pair has migrated to the alternate path
+    PathMigrated(QueuePair),
+    /// The queue pair failed to migrate to the alternate path
+    PathMigrationError(QueuePair),
+    /// The last work request of the queue pair attached to a shared receive queue has been consumed
+    QpLastWqeReached(QueuePair),
+    /// The number of outstanding receive requests dropped below the armed limit
+    SrqLimitReached(SharedReceiveQueue),
+    /// An error occurred on the shared receive queue
+    SrqError(SharedReceiveQueue),
+    /// The port became active
+    PortActive(u8),
+    /// The port became unavailable
+    PortError(u8),
+    /// The LID of the port changed
+    LidChange(u8),
+    /// The P_Key table of the port changed
+    PkeyChange(u8),
+    /// The subnet manager of the port changed
+    SmChange(u8),
+    /// The subnet manager requested to reregister the port
+    ClientReregister(u8),
+    /// The GID table of the port changed
+    GidChange(u8),
+    /// A fatal error occurred on the device, which is unusable until it is reopened
+    DeviceFatal,
+    /// An event which is not wrapped yet, with its raw event type
+    Other(u32),
+}
+
+impl AsyncEvent {
+    /// Returns `None` if the affiliated resource has been destroyed.
+    ///
+    /// # SAFETY
+    /// the event must not be acknowledged yet
+    unsafe fn from_raw(event: &ibverbs_sys::ibv_async_event) -> Option<Self> {
+        // SAFETY: the context is holding weak references to the affiliated resources,
+        // which are not destroyed before the event is acknowledged
+        let cq = || unsafe {
+            let cq = event.element.cq;
+            CompletionQueue::upgrade_cq_context((*cq).cq_context)
+        };
+        // SAFETY: same as above
+        let qp = || unsafe {
+            let qp = event.element.qp;
+            QueuePair::upgrade_qp_context((*qp).qp_context)
+        };
+        // SAFETY: same as above
+        let srq = || unsafe {
+            let srq = event.element.srq;
+            SharedReceiveQueue::from_srq_context((*srq).srq_context)
+        };
+        // SAFETY: port events carry the port number
+        let port = || unsafe { event.element.port_num.numeric_cast::<u8>() };
+
+        let ans = match event.event_type {
+            ibverbs_sys::IBV_EVENT_CQ_ERR => cq().map(AsyncEvent::CqError)?,
+            ibverbs_sys::IBV_EVENT_QP_FATAL => qp().map(AsyncEvent::QpFatal)?,
+            ibverbs_sys::IBV_EVENT_QP_REQ_ERR => qp().map(AsyncEvent::QpRequestError)?,
+            ibverbs_sys::IBV_EVENT_QP_ACCESS_ERR => qp().map(AsyncEvent::QpAccessError)?,
+            ibverbs_sys::IBV_EVENT_COMM_EST => qp().map(AsyncEvent::CommEstablished)?,
+            ibverbs_sys::IBV_EVENT_SQ_DRAINED => qp().map(AsyncEvent::SqDrained)?,
+            ibverbs_sys::IBV_EVENT_PATH_MIG => qp().map(AsyncEvent::PathMigrated)?,
+            ibverbs_sys::IBV_EVENT_PATH_MIG_ERR => qp().map(AsyncEvent::PathMigrationError)?,
+            ibverbs_sys::IBV_EVENT_QP_LAST_WQE_REACHED => qp().map(AsyncEvent::QpLastWqeReached)?,
+            ibverbs_sys::IBV_EVENT_SRQ_LIMIT_REACHED => srq().map(AsyncEvent::SrqLimitReached)?,
+            ibverbs_sys::IBV_EVENT_SRQ_ERR => srq().map(AsyncEvent::SrqError)?,
+            ibverbs_sys::IBV_EVENT_PORT_ACTIVE => AsyncEvent::PortActive(port()),
+            ibverbs_sys::IBV_EVENT_PORT_ERR => AsyncEvent::PortError(port()),
+            ibverbs_sys::IBV_EVENT_LID_CHANGE => AsyncEvent::LidChange(port()),
+            ibverbs_sys::IBV_EVENT_PKEY_CHANGE => AsyncEvent::PkeyChange(port()),
+            ibverbs_sys::IBV_EVENT_SM_CHANGE => AsyncEvent::SmChange(port()),
+            ibverbs_sys::IBV_EVENT_CLIENT_REREGISTER => AsyncEvent::ClientReregister(port()),
+            ibverbs_sys::IBV_EVENT_GID_CHANGE => AsyncEvent::GidChange(port()),
+            ibverbs_sys::IBV_EVENT_DEVICE_FATAL => AsyncEvent::DeviceFatal,
+            event_type => AsyncEvent::Other(event_type),
+        };
+        Some(ans)
+    }
+}
+
+/// Real time values of a device
+pub struct RtValues(ibverbs_sys::ibv_values_ex);
+
+impl RtValues {
+    /// Returns the raw hardware clock in cycles.
+    #[inline]
+    #[must_use]
+    pub fn raw_clock(&self) -> u64 {
+        let raw_clock = &self.0.raw_clock;
+        let sec: u64 = raw_clock.tv_sec.numeric_cast();
+        let nsec: u64 = raw_clock.tv_nsec.numeric_cast();
+        sec.wrapping_mul(1_000_000_000).wrapping_add(nsec)
+    }
 }
 
 struct Owner {
     ctx: ptr::NonNull<ibverbs_sys::ibv_context>,
+
+    cq_ref: sync::Mutex<WeakSet<cq::Owner>>,
+    qp_ref: sync::Mutex<WeakSet<qp::Owner>>,
+    srq_ref: sync::Mutex<WeakSet<srq::Owner>>,
 }
 
 /// SAFETY: owned type
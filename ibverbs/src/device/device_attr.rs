@@ -1,8 +1,13 @@
 use crate::ctx::Context;
+use crate::device::Guid;
 use crate::error::from_errno;
+use crate::qp::QueuePairType;
 
-use std::{io, mem};
+use std::{ffi, io, mem, ptr};
 
+use numeric_cast::NumericCast;
+
+#[repr(transparent)]
 pub struct DeviceAttr(ibverbs_sys::ibv_device_attr);
 
 impl DeviceAttr {
@@ -19,4 +24,596 @@ impl DeviceAttr {
             Ok(Self(device_attr))
         }
     }
+
+    pub(crate) fn from_ctype_ref(attr: &ibverbs_sys::ibv_device_attr) -> &Self {
+        // SAFETY: same repr
+        unsafe { mem::transmute(attr) }
+    }
+
+    /// Returns the firmware version.
+    ///
+    /// # Panics
+    /// + if the firmware version is not a valid utf8 string
+    #[inline]
+    #[must_use]
+    pub fn fw_ver(&self) -> &str {
+        // SAFETY: nul-terminated string filled by the driver
+        let fw_ver = unsafe { ffi::CStr::from_ptr(self.0.fw_ver.as_ptr()) };
+        fw_ver.to_str().expect("non-utf8 firmware version")
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn node_guid(&self) -> Guid {
+        Guid::from_bytes(self.0.node_guid.to_ne_bytes())
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn sys_image_guid(&self) -> Guid {
+        Guid::from_bytes(self.0.sys_image_guid.to_ne_bytes())
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn vendor_id(&self) -> u32 {
+        self.0.vendor_id
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn vendor_part_id(&self) -> u32 {
+        self.0.vendor_part_id
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn hw_ver(&self) -> u32 {
+        self.0.hw_ver
+    }
+
+    /// Returns the largest size of a memory region in bytes.
+    #[inline]
+    #[must_use]
+    pub fn max_mr_size(&self) -> u64 {
+        self.0.max_mr_size
+    }
+
+    /// Returns the supported page sizes as a bit mask.
+    #[inline]
+    #[must_use]
+    pub fn page_size_cap(&self) -> u64 {
+        self.0.page_size_cap
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn max_qp(&self) -> u32 {
+        self.0.max_qp.numeric_cast()
+    }
+
+    /// Returns the largest number of outstanding work requests of a queue.
+    #[inline]
+    #[must_use]
+    pub fn max_qp_wr(&self) -> u32 {
+        self.0.max_qp_wr.numeric_cast()
+    }
+
+    /// Returns the device capabilities.
+    ///
+    /// The extended capabilities are reported by [`DeviceAttrEx::device_cap_flags`].
+    #[inline]
+    #[must_use]
+    pub fn device_cap_flags(&self) -> DeviceCapFlags {
+        DeviceCapFlags::from_bits_truncate(u64::from(self.0.device_cap_flags))
+    }
+
+    /// Returns the largest number of scatter/gather elements of a work request.
+    #[inline]
+    #[must_use]
+    pub fn max_sge(&self) -> u32 {
+        self.0.max_sge.numeric_cast()
+    }
+
+    /// Returns the largest number of scatter/gather elements of an RDMA read.
+    #[inline]
+    #[must_use]
+    pub fn max_sge_rd(&self) -> u32 {
+        self.0.max_sge_rd.numeric_cast()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn max_cq(&self) -> u32 {
+        self.0.max_cq.numeric_cast()
+    }
+
+    /// Returns the largest number of entries of a completion queue.
+    #[inline]
+    #[must_use]
+    pub fn max_cqe(&self) -> u32 {
+        self.0.max_cqe.numeric_cast()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn max_mr(&self) -> u32 {
+        self.0.max_mr.numeric_cast()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn max_pd(&self) -> u32 {
+        self.0.max_pd.numeric_cast()
+    }
+
+    /// Returns the largest number of outstanding RDMA reads and atomic operations
+    /// which a queue pair can handle as the target.
+    #[inline]
+    #[must_use]
+    pub fn max_qp_rd_atom(&self) -> u32 {
+        self.0.max_qp_rd_atom.numeric_cast()
+    }
+
+    /// Returns the largest number of outstanding RDMA reads and atomic operations
+    /// which a queue pair can initiate.
+    #[inline]
+    #[must_use]
+    pub fn max_qp_init_rd_atom(&self) -> u32 {
+        self.0.max_qp_init_rd_atom.numeric_cast()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn max_res_rd_atom(&self) -> u32 {
+        self.0.max_res_rd_atom.numeric_cast()
+    }
+
+    /// Returns the atomicity guarantee of atomic operations.
+    ///
+    /// # Panics
+    /// + if the driver reports an unknown value
+    #[inline]
+    #[must_use]
+    pub fn atomic_cap(&self) -> AtomicCap {
+        AtomicCap::try_from(self.0.atomic_cap).unwrap()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn max_mw(&self) -> u32 {
+        self.0.max_mw.numeric_cast()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn max_ah(&self) -> u32 {
+        self.0.max_ah.numeric_cast()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn max_mcast_grp(&self) -> u32 {
+        self.0.max_mcast_grp.numeric_cast()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn max_mcast_qp_attach(&self) -> u32 {
+        self.0.max_mcast_qp_attach.numeric_cast()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn max_srq(&self) -> u32 {
+        self.0.max_srq.numeric_cast()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn max_srq_wr(&self) -> u32 {
+        self.0.max_srq_wr.numeric_cast()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn max_srq_sge(&self) -> u32 {
+        self.0.max_srq_sge.numeric_cast()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn max_pkeys(&self) -> u16 {
+        self.0.max_pkeys
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn local_ca_ack_delay(&self) -> u8 {
+        self.0.local_ca_ack_delay
+    }
+
+    /// Returns the number of physical ports.
+    #[inline]
+    #[must_use]
+    pub fn phys_port_cnt(&self) -> u8 {
+        self.0.phys_port_cnt
+    }
+}
+
+pub struct DeviceAttrEx(ibverbs_sys::ibv_device_attr_ex);
+
+impl DeviceAttrEx {
+    #[inline]
+    pub fn query(ctx: &Context) -> io::Result<Self> {
+        // SAFETY: ffi
+        unsafe {
+            let mut device_attr = mem::zeroed();
+            let context = ctx.ffi_ptr();
+            let ret = ibverbs_sys::ibv_query_device_ex(context, ptr::null(), &mut device_attr);
+            if ret != 0 {
+                return Err(from_errno(ret));
+            }
+            Ok(Self(device_attr))
+        }
+    }
+
+    /// Returns the attributes which are also reported by [`DeviceAttr`].
+    #[inline]
+    #[must_use]
+    pub fn orig_attr(&self) -> &DeviceAttr {
+        DeviceAttr::from_ctype_ref(&self.0.orig_attr)
+    }
+
+    /// Returns the device capabilities including the extended ones.
+    #[inline]
+    #[must_use]
+    pub fn device_cap_flags(&self) -> DeviceCapFlags {
+        DeviceCapFlags::from_bits_truncate(self.0.device_cap_flags_ex)
+    }
+
+    /// Returns the number of physical ports, which may exceed 255.
+    #[inline]
+    #[must_use]
+    pub fn phys_port_cnt(&self) -> u32 {
+        self.0.phys_port_cnt_ex
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn tso_caps(&self) -> TsoCaps {
+        TsoCaps(self.0.tso_caps)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn rss_caps(&self) -> RssCaps {
+        RssCaps(self.0.rss_caps)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn packet_pacing_caps(&self) -> PacketPacingCaps {
+        PacketPacingCaps(self.0.packet_pacing_caps)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn tm_caps(&self) -> TagMatchingCaps {
+        TagMatchingCaps(self.0.tm_caps)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn cq_moderation_caps(&self) -> CqModerationCaps {
+        CqModerationCaps {
+            max_cq_count: self.0.cq_mod_caps.max_cq_count,
+            max_cq_period: self.0.cq_mod_caps.max_cq_period,
+        }
+    }
+
+    /// Returns the operand sizes of atomic operations supported over PCIe.
+    #[inline]
+    #[must_use]
+    pub fn pci_atomic_caps(&self) -> PciAtomicCaps {
+        let caps = &self.0.pci_atomic_caps;
+        PciAtomicCaps {
+            fetch_add: PciAtomicOpSizes::from_bits_truncate(caps.fetch_add),
+            swap: PciAtomicOpSizes::from_bits_truncate(caps.swap),
+            compare_swap: PciAtomicOpSizes::from_bits_truncate(caps.compare_swap),
+        }
+    }
+
+    /// Returns the frequency of the core clock in kHz.
+    #[inline]
+    #[must_use]
+    pub fn hca_core_clock(&self) -> u64 {
+        self.0.hca_core_clock
+    }
+
+    /// Returns the valid bits of a completion timestamp.
+    #[inline]
+    #[must_use]
+    pub fn completion_timestamp_mask(&self) -> u64 {
+        self.0.completion_timestamp_mask
+    }
+
+    /// Returns the maximum size of device memory in bytes.
+    #[inline]
+    #[must_use]
+    pub fn max_dm_size(&self) -> u64 {
+        self.0.max_dm_size
+    }
+
+    /// Returns the general capabilities of on-demand paging.
+    #[inline]
+    #[must_use]
+    pub fn odp_general_caps(&self) -> OdpGeneralCaps {
+        OdpGeneralCaps::from_bits_truncate(self.0.odp_caps.general_caps)
+    }
+
+    /// Returns the operations supporting on-demand paging for the transport `qp_type`.
+    #[inline]
+    #[must_use]
+    pub fn odp_transport_caps(&self, qp_type: QueuePairType) -> OdpTransportCaps {
+        let caps = &self.0.odp_caps.per_transport_caps;
+        let bits = match qp_type {
+            QueuePairType::RC => caps.rc_odp_caps,
+            QueuePairType::UC => caps.uc_odp_caps,
+            QueuePairType::UD => caps.ud_odp_caps,
+            QueuePairType::XrcSend | QueuePairType::XrcRecv => self.0.xrc_odp_caps,
+            QueuePairType::Driver => 0,
+        };
+        OdpTransportCaps::from_bits_truncate(bits)
+    }
+}
+
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct OdpGeneralCaps: u64 {
+        const SUPPORT           = ibverbs_sys::IBV_ODP_SUPPORT as u64;
+        const SUPPORT_IMPLICIT  = ibverbs_sys::IBV_ODP_SUPPORT_IMPLICIT as u64;
+    }
+}
+
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct OdpTransportCaps: u32 {
+        const SEND      = ibverbs_sys::IBV_ODP_SUPPORT_SEND;
+        const RECV      = ibverbs_sys::IBV_ODP_SUPPORT_RECV;
+        const WRITE     = ibverbs_sys::IBV_ODP_SUPPORT_WRITE;
+        const READ      = ibverbs_sys::IBV_ODP_SUPPORT_READ;
+        const ATOMIC    = ibverbs_sys::IBV_ODP_SUPPORT_ATOMIC;
+        const SRQ_RECV  = ibverbs_sys::IBV_ODP_SUPPORT_SRQ_RECV;
+    }
+}
+
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct DeviceCapFlags: u64 {
+        const RESIZE_MAX_WR         = ibverbs_sys::IBV_DEVICE_RESIZE_MAX_WR as u64;
+        const BAD_PKEY_CNTR         = ibverbs_sys::IBV_DEVICE_BAD_PKEY_CNTR as u64;
+        const BAD_QKEY_CNTR         = ibverbs_sys::IBV_DEVICE_BAD_QKEY_CNTR as u64;
+        const RAW_MULTI             = ibverbs_sys::IBV_DEVICE_RAW_MULTI as u64;
+        const AUTO_PATH_MIG         = ibverbs_sys::IBV_DEVICE_AUTO_PATH_MIG as u64;
+        const CHANGE_PHY_PORT       = ibverbs_sys::IBV_DEVICE_CHANGE_PHY_PORT as u64;
+        const UD_AV_PORT_ENFORCE    = ibverbs_sys::IBV_DEVICE_UD_AV_PORT_ENFORCE as u64;
+        const CURR_QP_STATE_MOD     = ibverbs_sys::IBV_DEVICE_CURR_QP_STATE_MOD as u64;
+        const SHUTDOWN_PORT         = ibverbs_sys::IBV_DEVICE_SHUTDOWN_PORT as u64;
+        const INIT_TYPE             = ibverbs_sys::IBV_DEVICE_INIT_TYPE as u64;
+        const PORT_ACTIVE_EVENT     = ibverbs_sys::IBV_DEVICE_PORT_ACTIVE_EVENT as u64;
+        const SYS_IMAGE_GUID        = ibverbs_sys::IBV_DEVICE_SYS_IMAGE_GUID as u64;
+        const RC_RNR_NAK_GEN        = ibverbs_sys::IBV_DEVICE_RC_RNR_NAK_GEN as u64;
+        const SRQ_RESIZE            = ibverbs_sys::IBV_DEVICE_SRQ_RESIZE as u64;
+        const N_NOTIFY_CQ           = ibverbs_sys::IBV_DEVICE_N_NOTIFY_CQ as u64;
+        const MEM_WINDOW            = ibverbs_sys::IBV_DEVICE_MEM_WINDOW as u64;
+        const UD_IP_CSUM            = ibverbs_sys::IBV_DEVICE_UD_IP_CSUM as u64;
+        const XRC                   = ibverbs_sys::IBV_DEVICE_XRC as u64;
+        const MEM_MGT_EXTENSIONS    = ibverbs_sys::IBV_DEVICE_MEM_MGT_EXTENSIONS as u64;
+        const MEM_WINDOW_TYPE_2A    = ibverbs_sys::IBV_DEVICE_MEM_WINDOW_TYPE_2A as u64;
+        const MEM_WINDOW_TYPE_2B    = ibverbs_sys::IBV_DEVICE_MEM_WINDOW_TYPE_2B as u64;
+        const RC_IP_CSUM            = ibverbs_sys::IBV_DEVICE_RC_IP_CSUM as u64;
+        const RAW_IP_CSUM           = ibverbs_sys::IBV_DEVICE_RAW_IP_CSUM as u64;
+        const MANAGED_FLOW_STEERING = ibverbs_sys::IBV_DEVICE_MANAGED_FLOW_STEERING as u64;
+    }
+}
+
+/// The atomicity guarantee of atomic operations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum AtomicCap {
+    /// Atomic operations are not supported
+    None = ibverbs_sys::IBV_ATOMIC_NONE,
+    /// Atomic among the queue pairs of the device
+    Hca = ibverbs_sys::IBV_ATOMIC_HCA,
+    /// Atomic among the device and the other agents such as CPUs
+    Glob = ibverbs_sys::IBV_ATOMIC_GLOB,
+}
+
+impl TryFrom<ffi::c_uint> for AtomicCap {
+    type Error = ();
+
+    fn try_from(value: ffi::c_uint) -> Result<Self, Self::Error> {
+        match value {
+            ibverbs_sys::IBV_ATOMIC_NONE => Ok(AtomicCap::None),
+            ibverbs_sys::IBV_ATOMIC_HCA => Ok(AtomicCap::Hca),
+            ibverbs_sys::IBV_ATOMIC_GLOB => Ok(AtomicCap::Glob),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Tests a mask of supported queue pair types, which is indexed by the raw type.
+fn supports_qp_type(supported_qpts: u32, qp_type: QueuePairType) -> bool {
+    1u32.checked_shl(qp_type as u32)
+        .is_some_and(|bit| supported_qpts & bit != 0)
+}
+
+/// The capabilities of TCP segmentation offload
+#[derive(Clone, Copy)]
+pub struct TsoCaps(ibverbs_sys::ibv_tso_caps);
+
+impl TsoCaps {
+    /// Returns the largest size of a segmented packet in bytes.
+    #[inline]
+    #[must_use]
+    pub fn max_tso(&self) -> u32 {
+        self.0.max_tso
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn supports(&self, qp_type: QueuePairType) -> bool {
+        supports_qp_type(self.0.supported_qpts, qp_type)
+    }
+}
+
+/// The capabilities of receive side scaling
+#[derive(Clone, Copy)]
+pub struct RssCaps(ibverbs_sys::ibv_rss_caps);
+
+impl RssCaps {
+    #[inline]
+    #[must_use]
+    pub fn max_rwq_indirection_tables(&self) -> u32 {
+        self.0.max_rwq_indirection_tables
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn max_rwq_indirection_table_size(&self) -> u32 {
+        self.0.max_rwq_indirection_table_size
+    }
+
+    /// Returns the packet fields which can be hashed as a raw mask.
+    #[inline]
+    #[must_use]
+    pub fn rx_hash_fields_mask(&self) -> u64 {
+        self.0.rx_hash_fields_mask
+    }
+
+    /// Returns the supported hash functions as a raw mask.
+    #[inline]
+    #[must_use]
+    pub fn rx_hash_function(&self) -> u8 {
+        self.0.rx_hash_function
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn supports(&self, qp_type: QueuePairType) -> bool {
+        supports_qp_type(self.0.supported_qpts, qp_type)
+    }
+}
+
+/// The capabilities of rate limiting on queue pairs
+#[derive(Clone, Copy)]
+pub struct PacketPacingCaps(ibverbs_sys::ibv_packet_pacing_caps);
+
+impl PacketPacingCaps {
+    /// Returns the minimum rate limit in kbps.
+    #[inline]
+    #[must_use]
+    pub fn qp_rate_limit_min(&self) -> u32 {
+        self.0.qp_rate_limit_min
+    }
+
+    /// Returns the maximum rate limit in kbps.
+    #[inline]
+    #[must_use]
+    pub fn qp_rate_limit_max(&self) -> u32 {
+        self.0.qp_rate_limit_max
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn supports(&self, qp_type: QueuePairType) -> bool {
+        supports_qp_type(self.0.supported_qpts, qp_type)
+    }
+}
+
+/// The capabilities of tag matching offload
+#[derive(Clone, Copy)]
+pub struct TagMatchingCaps(ibverbs_sys::ibv_tm_caps);
+
+impl TagMatchingCaps {
+    /// Returns the largest size of a rendezvous header in bytes.
+    #[inline]
+    #[must_use]
+    pub fn max_rndv_hdr_size(&self) -> u32 {
+        self.0.max_rndv_hdr_size
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn max_num_tags(&self) -> u32 {
+        self.0.max_num_tags
+    }
+
+    /// Returns the largest number of outstanding tag list operations.
+    #[inline]
+    #[must_use]
+    pub fn max_ops(&self) -> u32 {
+        self.0.max_ops
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn max_sge(&self) -> u32 {
+        self.0.max_sge
+    }
+
+    /// Returns whether tag matching is supported on RC queue pairs.
+    #[inline]
+    #[must_use]
+    pub fn supports_rc(&self) -> bool {
+        self.0.flags & ibverbs_sys::IBV_TM_CAP_RC != 0
+    }
+}
+
+/// The capabilities of completion queue moderation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CqModerationCaps {
+    /// The largest number of completions per event
+    pub max_cq_count: u16,
+    /// The largest delay of an event in microseconds
+    pub max_cq_period: u16,
+}
+
+/// The operand sizes of atomic operations supported over PCIe
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PciAtomicCaps {
+    pub fetch_add: PciAtomicOpSizes,
+    pub swap: PciAtomicOpSizes,
+    pub compare_swap: PciAtomicOpSizes,
+}
+
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PciAtomicOpSizes: u16 {
+        const SIZE_4    = ibverbs_sys::IBV_PCI_ATOMIC_OPERATION_4_BYTE_SIZE_SUP as u16;
+        const SIZE_8    = ibverbs_sys::IBV_PCI_ATOMIC_OPERATION_8_BYTE_SIZE_SUP as u16;
+        const SIZE_16   = ibverbs_sys::IBV_PCI_ATOMIC_OPERATION_16_BYTE_SIZE_SUP as u16;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn qp_type_bits() {
+        let rc = 1 << ibverbs_sys::ibv_qp_type::IBV_QPT_RC;
+        let ud = 1 << ibverbs_sys::ibv_qp_type::IBV_QPT_UD;
+
+        assert!(supports_qp_type(rc | ud, QueuePairType::RC));
+        assert!(supports_qp_type(rc | ud, QueuePairType::UD));
+        assert!(!supports_qp_type(rc | ud, QueuePairType::UC));
+        assert!(!supports_qp_type(rc | ud, QueuePairType::XrcSend));
+        assert!(!supports_qp_type(0, QueuePairType::RC));
+
+        // the raw value of the driver type does not fit the mask
+        assert!(!supports_qp_type(u32::MAX, QueuePairType::Driver));
+        assert!(supports_qp_type(u32::MAX, QueuePairType::XrcRecv));
+    }
 }
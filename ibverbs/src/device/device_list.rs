@@ -1,9 +1,11 @@
-use super::Guid;
+use super::{DeviceSysfs, Guid};
 
 use crate::ctx::Context;
 use crate::error::last_error;
 
 use std::os::raw::c_int;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
 use std::{ffi, fmt, io, ops, ptr, slice};
 
 use numeric_cast::NumericCast;
@@ -73,6 +75,40 @@ impl DeviceList {
         // SAFETY: guaranteed by `DeviceList::available`
         unsafe { slice::from_raw_parts(self.arr.as_ptr(), self.len) }
     }
+
+    /// Finds the device whose kernel name is `name`, such as `mlx5_0`.
+    #[inline]
+    #[must_use]
+    pub fn find_by_name(&self, name: &str) -> Option<&Device> {
+        self.iter()
+            .find(|d| d.c_name().to_bytes() == name.as_bytes())
+    }
+
+    /// Finds the device whose node GUID is `guid`.
+    #[inline]
+    #[must_use]
+    pub fn find_by_guid(&self, guid: Guid) -> Option<&Device> {
+        self.iter().find(|d| d.guid() == guid)
+    }
+
+    /// Finds the device which owns the network interface `netdev`, such as `eth0`.
+    ///
+    /// Devices whose network interfaces can not be read are skipped.
+    /// The first such error is returned only if no device matches.
+    #[inline]
+    pub fn find_by_netdev(&self, netdev: &str) -> io::Result<Option<&Device>> {
+        let mut first_err = None;
+        for device in self.iter() {
+            match device.netdevs() {
+                Ok(netdevs) if netdevs.iter().any(|n| n == netdev) => return Ok(Some(device)),
+                Ok(_) => {}
+                Err(err) => {
+                    first_err.get_or_insert(err);
+                }
+            }
+        }
+        first_err.map_or(Ok(None), Err)
+    }
 }
 
 impl Drop for DeviceList {
@@ -130,4 +166,115 @@ impl Device {
     pub fn open(&self) -> io::Result<Context> {
         Context::open(self)
     }
+
+    #[inline]
+    #[must_use]
+    pub fn node_type(&self) -> NodeType {
+        // SAFETY: reading a immutable field of the device
+        NodeType::from_c_int(unsafe { (*self.ffi_ptr()).node_type })
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn transport_type(&self) -> TransportType {
+        // SAFETY: reading a immutable field of the device
+        TransportType::from_c_int(unsafe { (*self.ffi_ptr()).transport_type })
+    }
+
+    /// Returns the sysfs directory of the device, such as `/sys/class/infiniband/mlx5_0`.
+    #[inline]
+    #[must_use]
+    pub fn ibdev_path(&self) -> &Path {
+        // SAFETY: nul-terminated string filled by libibverbs
+        let path = unsafe { ffi::CStr::from_ptr((*self.ffi_ptr()).ibdev_path.as_ptr()) };
+        Path::new(ffi::OsStr::from_bytes(path.to_bytes()))
+    }
+
+    /// Returns the sysfs metadata of the device under the default sysfs root.
+    #[inline]
+    #[must_use]
+    pub fn sysfs(&self) -> DeviceSysfs {
+        DeviceSysfs::new(ffi::OsStr::from_bytes(self.c_name().to_bytes()))
+    }
+
+    /// Returns the names of the associated network interfaces.
+    #[inline]
+    pub fn netdevs(&self) -> io::Result<Vec<String>> {
+        self.sysfs().netdevs()
+    }
+
+    /// Returns the NUMA node of the device, or `None` if it is not bound to a NUMA node.
+    #[inline]
+    pub fn numa_node(&self) -> io::Result<Option<u32>> {
+        self.sysfs().numa_node()
+    }
+
+    /// Returns the PCI address of the device, or `None` if it is not a PCI device.
+    #[inline]
+    pub fn pci_address(&self) -> io::Result<Option<String>> {
+        self.sysfs().pci_address()
+    }
+}
+
+impl fmt::Debug for Device {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Device")
+            .field("name", &self.c_name())
+            .field("guid", &self.guid())
+            .finish()
+    }
+}
+
+/// The node type of a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeType {
+    Ca,
+    Switch,
+    Router,
+    Rnic,
+    Usnic,
+    UsnicUdp,
+    Unspecified,
+    Unknown,
+}
+
+impl NodeType {
+    fn from_c_int(val: ibverbs_sys::ibv_node_type) -> Self {
+        match val {
+            ibverbs_sys::IBV_NODE_CA => NodeType::Ca,
+            ibverbs_sys::IBV_NODE_SWITCH => NodeType::Switch,
+            ibverbs_sys::IBV_NODE_ROUTER => NodeType::Router,
+            ibverbs_sys::IBV_NODE_RNIC => NodeType::Rnic,
+            ibverbs_sys::IBV_NODE_USNIC => NodeType::Usnic,
+            ibverbs_sys::IBV_NODE_USNIC_UDP => NodeType::UsnicUdp,
+            ibverbs_sys::IBV_NODE_UNSPECIFIED => NodeType::Unspecified,
+            _ => NodeType::Unknown,
+        }
+    }
+}
+
+/// The transport type of a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportType {
+    /// InfiniBand, including RoCE
+    Ib,
+    Iwarp,
+    Usnic,
+    UsnicUdp,
+    Unspecified,
+    Unknown,
+}
+
+impl TransportType {
+    fn from_c_int(val: ibverbs_sys::ibv_transport_type) -> Self {
+        match val {
+            ibverbs_sys::IBV_TRANSPORT_IB => TransportType::Ib,
+            ibverbs_sys::IBV_TRANSPORT_IWARP => TransportType::Iwarp,
+            ibverbs_sys::IBV_TRANSPORT_USNIC => TransportType::Usnic,
+            ibverbs_sys::IBV_TRANSPORT_USNIC_UDP => TransportType::UsnicUdp,
+            ibverbs_sys::IBV_TRANSPORT_UNSPECIFIED => TransportType::Unspecified,
+            _ => TransportType::Unknown,
+        }
+    }
 }
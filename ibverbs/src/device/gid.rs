@@ -1,10 +1,14 @@
 use crate::ctx::Context;
-use crate::error::custom_error;
+use crate::device::PortAttr;
+use crate::error::{from_errno, last_error};
 use crate::utils::c_uint_to_u32;
 
 use std::os::raw::c_uint;
-use std::{io, mem, net};
+use std::{error, ffi, fmt, io, mem, net, str};
 
+use numeric_cast::NumericCast;
+
+#[derive(Clone, Copy)]
 #[repr(transparent)]
 pub struct GidEntry(ibverbs_sys::ibv_gid_entry);
 
@@ -13,19 +17,127 @@ impl GidEntry {
     pub fn query(ctx: &Context, port_num: u32, gid_index: u32) -> io::Result<Self> {
         // SAFETY: ffi
         unsafe {
-            // TODO: use MaybeUninit to avoid unnecessary initialization?
             let mut gid = mem::MaybeUninit::<Self>::uninit();
             let context = ctx.ffi_ptr();
             let entry = gid.as_mut_ptr().cast::<ibverbs_sys::ibv_gid_entry>();
-            let flags = 0; // ASK: what is this?
-            let ret = ibverbs_sys::_ibv_query_gid_ex(context, port_num, gid_index, entry, flags);
+            let flags = 0; // reserved
+            let ret = ibverbs_sys::ibv_query_gid_ex(context, port_num, gid_index, entry, flags);
             if ret != 0 {
-                return Err(custom_error("failed to query gid entry"));
+                return Err(from_errno(ret));
             }
             Ok(gid.assume_init())
         }
     }
 
+    /// Queries the valid entries of the GID table of the port `port_num`.
+    ///
+    /// It falls back to querying the entries one by one
+    /// if the device does not support querying the whole table.
+    pub(crate) fn query_table(ctx: &Context, port_num: u8) -> io::Result<Vec<Self>> {
+        match Self::query_whole_table(ctx) {
+            Ok(mut entries) => {
+                entries.retain(|e| e.port_num() == u32::from(port_num));
+                Ok(entries)
+            }
+            Err(err) if err.raw_os_error() == Some(libc::EOPNOTSUPP) => {
+                let attr = PortAttr::query(ctx, port_num)?;
+                let mut entries = Vec::new();
+                for gid_index in 0..attr.gid_table_len() {
+                    match Self::query(ctx, port_num.into(), gid_index) {
+                        Ok(entry) if !entry.gid().is_zero() => entries.push(entry),
+                        Ok(_) => {}
+                        Err(err) if err.raw_os_error() == Some(libc::ENODATA) => {}
+                        Err(err) => return Err(err),
+                    }
+                }
+                Ok(entries)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Queries the valid entries of all ports.
+    fn query_whole_table(ctx: &Context) -> io::Result<Vec<Self>> {
+        let mut max_entries = 0;
+        for port in ctx.ports()? {
+            let (_, attr) = port?;
+            max_entries += attr.gid_table_len().numeric_cast::<usize>();
+        }
+        let mut entries: Vec<Self> = Vec::with_capacity(max_entries);
+        // SAFETY: ffi
+        unsafe {
+            let context = ctx.ffi_ptr();
+            let ptr = entries.as_mut_ptr().cast::<ibverbs_sys::ibv_gid_entry>();
+            let flags = 0; // reserved
+            let ret = ibverbs_sys::ibv_query_gid_table(context, ptr, max_entries, flags);
+            if ret < 0 {
+                return Err(from_errno((-ret).numeric_cast()));
+            }
+            entries.set_len(ret.numeric_cast());
+        }
+        Ok(entries)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn gid_index(&self) -> u32 {
+        self.0.gid_index
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn port_num(&self) -> u32 {
+        self.0.port_num
+    }
+
+    /// Returns the interface index of the associated network device.
+    ///
+    /// Returns `None` for InfiniBand ports.
+    #[inline]
+    #[must_use]
+    pub fn ndev_ifindex(&self) -> Option<u32> {
+        Some(self.0.ndev_ifindex).filter(|&ifindex| ifindex != 0)
+    }
+
+    /// Returns whether the entry is a RoCE v2 GID which is routable,
+    /// skipping empty and link-local GIDs.
+    fn is_routable_roce_v2(&self) -> bool {
+        let gid = self.gid();
+        self.gid_type() == GidType::RoceV2 && !gid.is_zero() && !gid.is_link_local()
+    }
+
+    /// Selects the RoCE v2 GID whose address is `ip`.
+    ///
+    /// IPv4 addresses are matched with their IPv4-mapped IPv6 form.
+    pub(crate) fn select_roce_v2_by_ip(entries: &[Self], ip: net::IpAddr) -> Option<Self> {
+        let ip = match ip {
+            net::IpAddr::V4(v4) => v4.to_ipv6_mapped(),
+            net::IpAddr::V6(v6) => v6,
+        };
+        entries
+            .iter()
+            .find(|e| e.is_routable_roce_v2() && e.gid().to_ipv6_addr() == ip)
+            .copied()
+    }
+
+    /// Selects the first RoCE v2 GID associated with the network interface `ifname`.
+    pub(crate) fn select_roce_v2_by_ifname(
+        entries: &[Self],
+        ifname: &str,
+    ) -> io::Result<Option<Self>> {
+        let ifname =
+            ffi::CString::new(ifname).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+        // SAFETY: ffi
+        let ifindex = unsafe { libc::if_nametoindex(ifname.as_ptr()) };
+        if ifindex == 0 {
+            return Err(last_error());
+        }
+        Ok(entries
+            .iter()
+            .find(|e| e.is_routable_roce_v2() && e.ndev_ifindex() == Some(ifindex))
+            .copied())
+    }
+
     #[inline]
     #[must_use]
     pub fn gid_type(&self) -> GidType {
@@ -58,6 +170,70 @@ impl GidType {
     }
 }
 
+/// Formats the type as `IB`, `RoCE v1` or `RoCE v2`, as shown by rdma-core tools.
+impl fmt::Display for GidType {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            GidType::IB => "IB",
+            GidType::RoceV1 => "RoCE v1",
+            GidType::RoceV2 => "RoCE v2",
+        };
+        f.write_str(s)
+    }
+}
+
+impl str::FromStr for GidType {
+    type Err = ParseGidTypeError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "IB" => Ok(GidType::IB),
+            "RoCE v1" => Ok(GidType::RoceV1),
+            "RoCE v2" => Ok(GidType::RoceV2),
+            _ => Err(ParseGidTypeError(())),
+        }
+    }
+}
+
+/// An error which is returned when parsing a [`GidType`] fails
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseGidTypeError(());
+
+impl fmt::Display for ParseGidTypeError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid GID type")
+    }
+}
+
+impl error::Error for ParseGidTypeError {}
+
+/// Serializes the type as its display string, such as `RoCE v2`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for GidType {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for GidType {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 #[derive(Clone, Copy)]
 #[repr(transparent)]
 pub struct Gid(ibverbs_sys::ibv_gid);
@@ -82,6 +258,28 @@ impl Gid {
         net::Ipv6Addr::from(*self.as_bytes())
     }
 
+    /// Returns the IPv4 address if the GID is IPv4-mapped, as used by RoCE v2.
+    #[inline]
+    #[must_use]
+    pub fn to_ipv4_addr(&self) -> Option<net::Ipv4Addr> {
+        self.to_ipv6_addr().to_ipv4_mapped()
+    }
+
+    /// Returns whether all bytes are zero, which marks an empty GID table entry.
+    #[inline]
+    #[must_use]
+    pub fn is_zero(&self) -> bool {
+        self.as_bytes().iter().all(|&b| b == 0)
+    }
+
+    /// Returns whether the GID is in the link-local prefix `fe80::/10`.
+    #[inline]
+    #[must_use]
+    pub fn is_link_local(&self) -> bool {
+        let bytes = self.as_bytes();
+        bytes[0] == 0xfe && bytes[1] & 0xc0 == 0x80
+    }
+
     #[inline]
     #[must_use]
     pub const fn subnet_prefix(&self) -> u64 {
@@ -105,3 +303,218 @@ impl PartialEq for Gid {
 }
 
 impl Eq for Gid {}
+
+impl From<net::Ipv6Addr> for Gid {
+    #[inline]
+    fn from(addr: net::Ipv6Addr) -> Self {
+        Self::from_bytes(addr.octets())
+    }
+}
+
+/// Converts to the IPv4-mapped GID, as used by RoCE v2.
+impl From<net::Ipv4Addr> for Gid {
+    #[inline]
+    fn from(addr: net::Ipv4Addr) -> Self {
+        Self::from(addr.to_ipv6_mapped())
+    }
+}
+
+impl From<Gid> for net::Ipv6Addr {
+    #[inline]
+    fn from(gid: Gid) -> Self {
+        gid.to_ipv6_addr()
+    }
+}
+
+/// Formats the GID as an IPv6 address.
+///
+/// The alternate flag (`{:#}`) prints all eight groups of four hex digits,
+/// such as `fe80:0000:0000:0000:0202:c9ff:fe00:0001`.
+impl fmt::Display for Gid {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            let segments = self.to_ipv6_addr().segments();
+            for (i, segment) in segments.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(":")?;
+                }
+                write!(f, "{segment:04x}")?;
+            }
+            Ok(())
+        } else {
+            fmt::Display::fmt(&self.to_ipv6_addr(), f)
+        }
+    }
+}
+
+impl fmt::Debug for Gid {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Gid({self})")
+    }
+}
+
+/// Parses the GID as an IPv6 address.
+impl str::FromStr for Gid {
+    type Err = net::AddrParseError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<net::Ipv6Addr>().map(Self::from)
+    }
+}
+
+/// Serializes the GID as a string in human-readable formats, or as bytes otherwise.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Gid {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serde::Serialize::serialize(self.as_bytes(), serializer)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Gid {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+            s.parse().map_err(serde::de::Error::custom)
+        } else {
+            <[u8; 16] as serde::Deserialize>::deserialize(deserializer).map(Self::from_bytes)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(gid: Gid, gid_index: u32, gid_type: c_uint) -> GidEntry {
+        GidEntry(ibverbs_sys::ibv_gid_entry {
+            gid: gid.0,
+            gid_index,
+            port_num: 1,
+            gid_type,
+            ndev_ifindex: 0,
+        })
+    }
+
+    fn ipv4(s: &str) -> Gid {
+        Gid::from(s.parse::<net::Ipv4Addr>().unwrap())
+    }
+
+    fn ipv6(s: &str) -> Gid {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn select_by_ipv4() {
+        let entries = [
+            entry(ipv6("fe80::1"), 0, ibverbs_sys::IBV_GID_TYPE_ROCE_V1),
+            entry(ipv6("fe80::1"), 1, ibverbs_sys::IBV_GID_TYPE_ROCE_V2),
+            entry(ipv4("192.168.1.2"), 2, ibverbs_sys::IBV_GID_TYPE_ROCE_V1),
+            entry(ipv4("192.168.1.2"), 3, ibverbs_sys::IBV_GID_TYPE_ROCE_V2),
+        ];
+        let ip = net::IpAddr::from([192, 168, 1, 2]);
+        let selected = GidEntry::select_roce_v2_by_ip(&entries, ip).unwrap();
+        assert_eq!(selected.gid_index(), 3);
+        assert_eq!(selected.gid_type(), GidType::RoceV2);
+
+        let ip = net::IpAddr::from([192, 168, 1, 3]);
+        assert!(GidEntry::select_roce_v2_by_ip(&entries, ip).is_none());
+    }
+
+    #[test]
+    fn select_by_ipv6() {
+        let entries = [
+            entry(ipv6("2001:db8::1"), 0, ibverbs_sys::IBV_GID_TYPE_ROCE_V1),
+            entry(ipv6("2001:db8::1"), 1, ibverbs_sys::IBV_GID_TYPE_ROCE_V2),
+        ];
+        let ip = net::IpAddr::V6("2001:db8::1".parse().unwrap());
+        let selected = GidEntry::select_roce_v2_by_ip(&entries, ip).unwrap();
+        assert_eq!(selected.gid_index(), 1);
+    }
+
+    #[test]
+    fn skip_unroutable() {
+        let entries = [
+            entry(ipv6("::"), 0, ibverbs_sys::IBV_GID_TYPE_ROCE_V2),
+            entry(ipv6("fe80::1"), 1, ibverbs_sys::IBV_GID_TYPE_ROCE_V2),
+            entry(ipv6("febf::1"), 2, ibverbs_sys::IBV_GID_TYPE_ROCE_V2),
+            entry(ipv4("10.0.0.1"), 3, ibverbs_sys::IBV_GID_TYPE_ROCE_V1),
+        ];
+        assert!(!entries[0].is_routable_roce_v2());
+        assert!(!entries[1].is_routable_roce_v2());
+        assert!(!entries[2].is_routable_roce_v2());
+        assert!(!entries[3].is_routable_roce_v2());
+
+        let ip = net::IpAddr::V6(net::Ipv6Addr::UNSPECIFIED);
+        assert!(GidEntry::select_roce_v2_by_ip(&entries, ip).is_none());
+        let ip = net::IpAddr::V6("fe80::1".parse().unwrap());
+        assert!(GidEntry::select_roce_v2_by_ip(&entries, ip).is_none());
+        let ip = net::IpAddr::from([10, 0, 0, 1]);
+        assert!(GidEntry::select_roce_v2_by_ip(&entries, ip).is_none());
+    }
+
+    #[test]
+    fn link_local() {
+        assert!(ipv6("fe80::1").is_link_local());
+        assert!(ipv6("febf:ffff::1").is_link_local());
+        assert!(!ipv6("fec0::1").is_link_local());
+        assert!(!ipv6("fe00::1").is_link_local());
+        assert!(!ipv4("169.254.0.1").is_link_local());
+    }
+
+    #[test]
+    fn gid_type_round_trip() {
+        for gid_type in [GidType::IB, GidType::RoceV1, GidType::RoceV2] {
+            assert_eq!(gid_type.to_string().parse::<GidType>(), Ok(gid_type));
+        }
+        assert_eq!(GidType::RoceV2.to_string(), "RoCE v2");
+        assert!("RoCEv2".parse::<GidType>().is_err());
+        assert!("roce v2".parse::<GidType>().is_err());
+    }
+
+    #[test]
+    fn gid_round_trip() {
+        let gid = ipv6("fe80::202:c9ff:fe00:1");
+        assert_eq!(gid.to_string(), "fe80::202:c9ff:fe00:1");
+        assert_eq!(gid.to_string().parse::<Gid>().unwrap(), gid);
+
+        let full = format!("{gid:#}");
+        assert_eq!(full, "fe80:0000:0000:0000:0202:c9ff:fe00:0001");
+        assert_eq!(full.parse::<Gid>().unwrap(), gid);
+
+        assert!("fe80::1::1".parse::<Gid>().is_err());
+        assert!("192.168.1.2".parse::<Gid>().is_err());
+    }
+
+    #[test]
+    fn gid_from_ipv4() {
+        let gid = ipv4("192.168.1.2");
+        let mut bytes = [0; 16];
+        bytes[10..].copy_from_slice(&[0xff, 0xff, 192, 168, 1, 2]);
+        assert_eq!(gid.as_bytes(), &bytes);
+        assert_eq!(gid.to_string(), "::ffff:192.168.1.2");
+        assert_eq!(gid.to_ipv4_addr(), Some(net::Ipv4Addr::new(192, 168, 1, 2)));
+        assert_eq!(ipv6("fe80::1").to_ipv4_addr(), None);
+    }
+
+    #[test]
+    fn zero() {
+        assert!(Gid::from_bytes([0; 16]).is_zero());
+        assert!(!ipv6("::1").is_zero());
+    }
+}
@@ -1,3 +1,5 @@
+use std::{error, fmt, str};
+
 /// A RDMA device guid
 #[derive(Clone, Copy, PartialEq, Eq)]
 #[repr(C, align(8))]
@@ -18,3 +20,129 @@ impl Guid {
         &self.0
     }
 }
+
+/// Formats the GUID as four colon-separated groups of hex digits,
+/// such as `0002:c903:0001:2345`.
+impl fmt::Display for Guid {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = hex_simd::encode_to_string(&self.0, hex_simd::AsciiCase::Lower);
+        let (a, rest) = hex.split_at(4);
+        let (b, rest) = rest.split_at(4);
+        let (c, d) = rest.split_at(4);
+        write!(f, "{a}:{b}:{c}:{d}")
+    }
+}
+
+impl fmt::Debug for Guid {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Guid({self})")
+    }
+}
+
+/// Parses four colon-separated groups of four hex digits, or sixteen hex digits.
+impl str::FromStr for Guid {
+    type Err = ParseGuidError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex: String = if s.len() == 19 {
+            let groups: Vec<&str> = s.split(':').collect();
+            if groups.len() != 4 || groups.iter().any(|g| g.len() != 4) {
+                return Err(ParseGuidError(()));
+            }
+            groups.concat()
+        } else {
+            s.to_owned()
+        };
+        if hex.len() != 16 {
+            return Err(ParseGuidError(()));
+        }
+        let bytes = hex_simd::decode_to_vec(hex.as_bytes()).map_err(|_| ParseGuidError(()))?;
+        let bytes = bytes.try_into().map_err(|_| ParseGuidError(()))?;
+        Ok(Self(bytes))
+    }
+}
+
+/// An error which is returned when parsing a [`Guid`] fails
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseGuidError(());
+
+impl fmt::Display for ParseGuidError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid GUID syntax")
+    }
+}
+
+impl error::Error for ParseGuidError {}
+
+/// Serializes the GUID as a string in human-readable formats, or as bytes otherwise.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Guid {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serde::Serialize::serialize(self.as_bytes(), serializer)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Guid {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+            s.parse().map_err(serde::de::Error::custom)
+        } else {
+            <[u8; 8] as serde::Deserialize>::deserialize(deserializer).map(Self::from_bytes)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BYTES: [u8; 8] = [0x00, 0x02, 0xc9, 0x03, 0x00, 0x01, 0x23, 0x45];
+
+    #[test]
+    fn colon_form() {
+        let guid = Guid::from_bytes(BYTES);
+        assert_eq!(guid.to_string(), "0002:c903:0001:2345");
+        assert_eq!("0002:c903:0001:2345".parse(), Ok(guid));
+        assert_eq!("0002:C903:0001:2345".parse(), Ok(guid));
+    }
+
+    #[test]
+    fn bare_form() {
+        let guid = Guid::from_bytes(BYTES);
+        assert_eq!("0002c90300012345".parse(), Ok(guid));
+    }
+
+    #[test]
+    fn malformed() {
+        for s in [
+            "",
+            "0002:c903:0001:234",
+            "0002:c903:0001:23456",
+            "0002:c903:00012:345",
+            "0002-c903-0001-2345",
+            "0002c9030001234",
+            "0002c903000123456",
+            "0002c903000123zz",
+        ] {
+            assert_eq!(s.parse::<Guid>(), Err(ParseGuidError(())), "{s}");
+        }
+    }
+}
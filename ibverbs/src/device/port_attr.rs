@@ -1,7 +1,7 @@
 use crate::ctx::Context;
 use crate::error::from_errno;
 
-use ibverbs_sys::ibv_port_state;
+use ibverbs_sys::{ibv_port_cap_flags, ibv_port_state};
 use std::{ffi, io, mem};
 
 pub struct PortAttr(ibverbs_sys::ibv_port_attr);
@@ -51,6 +51,273 @@ impl PortAttr {
     pub fn active_mtu(&self) -> Mtu {
         Mtu::try_from(self.0.active_mtu).unwrap()
     }
+
+    #[inline]
+    #[must_use]
+    pub fn max_mtu(&self) -> Mtu {
+        Mtu::try_from(self.0.max_mtu).unwrap()
+    }
+
+    /// Returns the largest size of a message in bytes.
+    #[inline]
+    #[must_use]
+    pub fn max_msg_sz(&self) -> u32 {
+        self.0.max_msg_sz
+    }
+
+    /// Returns the LID of the subnet manager.
+    #[inline]
+    #[must_use]
+    pub fn sm_lid(&self) -> u16 {
+        self.0.sm_lid
+    }
+
+    /// Returns the service level of the subnet manager.
+    #[inline]
+    #[must_use]
+    pub fn sm_sl(&self) -> u8 {
+        self.0.sm_sl
+    }
+
+    /// Returns the LID mask control, which assigns `2^lmc` LIDs to the port.
+    #[inline]
+    #[must_use]
+    pub fn lmc(&self) -> u8 {
+        self.0.lmc
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn pkey_table_len(&self) -> u16 {
+        self.0.pkey_tbl_len
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn max_vl_num(&self) -> u8 {
+        self.0.max_vl_num
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn subnet_timeout(&self) -> u8 {
+        self.0.subnet_timeout
+    }
+
+    /// Returns the active width and speed of the link.
+    ///
+    /// Returns `None` if the link is down or the driver reports unknown values.
+    /// XDR lanes (200 Gbit/s) do not fit `active_speed` and are only reported
+    /// through `active_speed_ex`, which is newer than the rdma-core this crate
+    /// builds against, so XDR links return `None` as well.
+    #[inline]
+    #[must_use]
+    pub fn link_speed(&self) -> Option<LinkSpeed> {
+        Some(LinkSpeed {
+            width: LinkWidth::try_from(self.0.active_width).ok()?,
+            lane_speed: LaneSpeed::try_from(self.0.active_speed).ok()?,
+        })
+    }
+
+    /// Returns the physical state of the port.
+    ///
+    /// Returns `None` if the driver reports an unknown value.
+    #[inline]
+    #[must_use]
+    pub fn phys_state(&self) -> Option<PhysState> {
+        PhysState::try_from(self.0.phys_state).ok()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn cap_flags(&self) -> PortCapFlags {
+        PortCapFlags::from_bits_truncate(self.0.port_cap_flags)
+    }
+
+    /// Returns the extended capabilities,
+    /// which are valid if [`PortCapFlags::CAP_MASK2_SUP`] is set.
+    #[inline]
+    #[must_use]
+    pub fn cap_flags2(&self) -> PortCapFlags2 {
+        PortCapFlags2::from_bits_truncate(self.0.port_cap_flags2)
+    }
+}
+
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PortCapFlags: u32 {
+        const SM                        = ibv_port_cap_flags::IBV_PORT_SM.0;
+        const NOTICE_SUP                = ibv_port_cap_flags::IBV_PORT_NOTICE_SUP.0;
+        const TRAP_SUP                  = ibv_port_cap_flags::IBV_PORT_TRAP_SUP.0;
+        const OPT_IPD_SUP               = ibv_port_cap_flags::IBV_PORT_OPT_IPD_SUP.0;
+        const AUTO_MIGR_SUP             = ibv_port_cap_flags::IBV_PORT_AUTO_MIGR_SUP.0;
+        const SL_MAP_SUP                = ibv_port_cap_flags::IBV_PORT_SL_MAP_SUP.0;
+        const MKEY_NVRAM                = ibv_port_cap_flags::IBV_PORT_MKEY_NVRAM.0;
+        const PKEY_NVRAM                = ibv_port_cap_flags::IBV_PORT_PKEY_NVRAM.0;
+        const LED_INFO_SUP              = ibv_port_cap_flags::IBV_PORT_LED_INFO_SUP.0;
+        const SYS_IMAGE_GUID_SUP        = ibv_port_cap_flags::IBV_PORT_SYS_IMAGE_GUID_SUP.0;
+        const PKEY_SW_EXT_PORT_TRAP_SUP = ibv_port_cap_flags::IBV_PORT_PKEY_SW_EXT_PORT_TRAP_SUP.0;
+        const EXTENDED_SPEEDS_SUP       = ibv_port_cap_flags::IBV_PORT_EXTENDED_SPEEDS_SUP.0;
+        const CAP_MASK2_SUP             = ibv_port_cap_flags::IBV_PORT_CAP_MASK2_SUP.0;
+        const CM_SUP                    = ibv_port_cap_flags::IBV_PORT_CM_SUP.0;
+        const SNMP_TUNNEL_SUP           = ibv_port_cap_flags::IBV_PORT_SNMP_TUNNEL_SUP.0;
+        const REINIT_SUP                = ibv_port_cap_flags::IBV_PORT_REINIT_SUP.0;
+        const DEVICE_MGMT_SUP           = ibv_port_cap_flags::IBV_PORT_DEVICE_MGMT_SUP.0;
+        const VENDOR_CLASS_SUP          = ibv_port_cap_flags::IBV_PORT_VENDOR_CLASS_SUP.0;
+        const DR_NOTICE_SUP             = ibv_port_cap_flags::IBV_PORT_DR_NOTICE_SUP.0;
+        const CAP_MASK_NOTICE_SUP       = ibv_port_cap_flags::IBV_PORT_CAP_MASK_NOTICE_SUP.0;
+        const BOOT_MGMT_SUP             = ibv_port_cap_flags::IBV_PORT_BOOT_MGMT_SUP.0;
+        const LINK_LATENCY_SUP          = ibv_port_cap_flags::IBV_PORT_LINK_LATENCY_SUP.0;
+        const CLIENT_REG_SUP            = ibv_port_cap_flags::IBV_PORT_CLIENT_REG_SUP.0;
+        const IP_BASED_GIDS             = ibv_port_cap_flags::IBV_PORT_IP_BASED_GIDS.0;
+    }
+}
+
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PortCapFlags2: u16 {
+        const SET_NODE_DESC_SUP             = ibverbs_sys::IBV_PORT_SET_NODE_DESC_SUP as u16;
+        const INFO_EXT_SUP                  = ibverbs_sys::IBV_PORT_INFO_EXT_SUP as u16;
+        const VIRT_SUP                      = ibverbs_sys::IBV_PORT_VIRT_SUP as u16;
+        const SWITCH_PORT_STATE_TABLE_SUP   = ibverbs_sys::IBV_PORT_SWITCH_PORT_STATE_TABLE_SUP as u16;
+        const LINK_WIDTH_2X_SUP             = ibverbs_sys::IBV_PORT_LINK_WIDTH_2X_SUP as u16;
+        const LINK_SPEED_HDR_SUP            = ibverbs_sys::IBV_PORT_LINK_SPEED_HDR_SUP as u16;
+    }
+}
+
+/// The active width and speed of a link
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkSpeed {
+    pub width: LinkWidth,
+    pub lane_speed: LaneSpeed,
+}
+
+impl LinkSpeed {
+    /// Returns the data rate of the link in Gbit/s.
+    #[inline]
+    #[must_use]
+    pub fn gbps(&self) -> f64 {
+        f64::from(self.width.lanes()) * self.lane_speed.gbps()
+    }
+}
+
+/// The number of lanes of a link
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum LinkWidth {
+    X1 = 1,
+    X4 = 2,
+    X8 = 4,
+    X12 = 8,
+    X2 = 16,
+}
+
+impl LinkWidth {
+    #[inline]
+    #[must_use]
+    pub fn lanes(self) -> u8 {
+        match self {
+            LinkWidth::X1 => 1,
+            LinkWidth::X2 => 2,
+            LinkWidth::X4 => 4,
+            LinkWidth::X8 => 8,
+            LinkWidth::X12 => 12,
+        }
+    }
+}
+
+impl TryFrom<u8> for LinkWidth {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(LinkWidth::X1),
+            2 => Ok(LinkWidth::X4),
+            4 => Ok(LinkWidth::X8),
+            8 => Ok(LinkWidth::X12),
+            16 => Ok(LinkWidth::X2),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The signaling rate of a lane
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum LaneSpeed {
+    Sdr = 1,
+    Ddr = 2,
+    Qdr = 4,
+    Fdr10 = 8,
+    Fdr = 16,
+    Edr = 32,
+    Hdr = 64,
+    Ndr = 128,
+}
+
+impl LaneSpeed {
+    /// Returns the data rate of a lane in Gbit/s, as reported by `ibv_devinfo`.
+    #[inline]
+    #[must_use]
+    pub fn gbps(self) -> f64 {
+        match self {
+            LaneSpeed::Sdr => 2.5,
+            LaneSpeed::Ddr => 5.0,
+            LaneSpeed::Qdr | LaneSpeed::Fdr10 => 10.0,
+            LaneSpeed::Fdr => 14.0,
+            LaneSpeed::Edr => 25.0,
+            LaneSpeed::Hdr => 50.0,
+            LaneSpeed::Ndr => 100.0,
+        }
+    }
+}
+
+impl TryFrom<u8> for LaneSpeed {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(LaneSpeed::Sdr),
+            2 => Ok(LaneSpeed::Ddr),
+            4 => Ok(LaneSpeed::Qdr),
+            8 => Ok(LaneSpeed::Fdr10),
+            16 => Ok(LaneSpeed::Fdr),
+            32 => Ok(LaneSpeed::Edr),
+            64 => Ok(LaneSpeed::Hdr),
+            128 => Ok(LaneSpeed::Ndr),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The physical state of a port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PhysState {
+    Sleep = 1,
+    Polling = 2,
+    Disabled = 3,
+    PortConfigurationTraining = 4,
+    LinkUp = 5,
+    LinkErrorRecovery = 6,
+    PhyTest = 7,
+}
+
+impl TryFrom<u8> for PhysState {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(PhysState::Sleep),
+            2 => Ok(PhysState::Polling),
+            3 => Ok(PhysState::Disabled),
+            4 => Ok(PhysState::PortConfigurationTraining),
+            5 => Ok(PhysState::LinkUp),
+            6 => Ok(PhysState::LinkErrorRecovery),
+            7 => Ok(PhysState::PhyTest),
+            _ => Err(()),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -100,7 +367,8 @@ impl TryFrom<ffi::c_uint> for LinkLayer {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u32)]
 pub enum Mtu {
     Mtu256 = ibverbs_sys::IBV_MTU_256,
@@ -126,9 +394,89 @@ impl TryFrom<ffi::c_uint> for Mtu {
 }
 
 impl Mtu {
+    pub(crate) fn to_c_uint(self) -> ffi::c_uint {
+        self as ffi::c_uint
+    }
+
     #[inline]
     #[must_use]
     pub fn size(self) -> usize {
         1usize.wrapping_shl((self as u32).wrapping_add(7))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn link_width() {
+        let table = [
+            (1, LinkWidth::X1, 1),
+            (2, LinkWidth::X4, 4),
+            (4, LinkWidth::X8, 8),
+            (8, LinkWidth::X12, 12),
+            (16, LinkWidth::X2, 2),
+        ];
+        for (raw, width, lanes) in table {
+            assert_eq!(LinkWidth::try_from(raw), Ok(width));
+            assert_eq!(width.lanes(), lanes);
+        }
+        for raw in [0, 3, 32, 255] {
+            assert_eq!(LinkWidth::try_from(raw), Err(()));
+        }
+    }
+
+    #[test]
+    fn lane_speed() {
+        let table = [
+            (1, LaneSpeed::Sdr, 2.5),
+            (2, LaneSpeed::Ddr, 5.0),
+            (4, LaneSpeed::Qdr, 10.0),
+            (8, LaneSpeed::Fdr10, 10.0),
+            (16, LaneSpeed::Fdr, 14.0),
+            (32, LaneSpeed::Edr, 25.0),
+            (64, LaneSpeed::Hdr, 50.0),
+            (128, LaneSpeed::Ndr, 100.0),
+        ];
+        for (raw, speed, gbps) in table {
+            assert_eq!(LaneSpeed::try_from(raw), Ok(speed));
+            assert!((speed.gbps() - gbps).abs() < f64::EPSILON);
+        }
+        for raw in [0, 3, 255] {
+            assert_eq!(LaneSpeed::try_from(raw), Err(()));
+        }
+    }
+
+    #[test]
+    fn link_speed_gbps() {
+        let table = [
+            (LinkWidth::X1, LaneSpeed::Sdr, 2.5),
+            (LinkWidth::X4, LaneSpeed::Qdr, 40.0),
+            (LinkWidth::X12, LaneSpeed::Qdr, 120.0),
+            (LinkWidth::X4, LaneSpeed::Fdr, 56.0),
+            (LinkWidth::X4, LaneSpeed::Edr, 100.0),
+            (LinkWidth::X2, LaneSpeed::Hdr, 100.0),
+            (LinkWidth::X4, LaneSpeed::Hdr, 200.0),
+            (LinkWidth::X4, LaneSpeed::Ndr, 400.0),
+        ];
+        for (width, lane_speed, gbps) in table {
+            let speed = LinkSpeed { width, lane_speed };
+            assert!((speed.gbps() - gbps).abs() < f64::EPSILON, "{speed:?}");
+        }
+    }
+
+    #[test]
+    fn link_speed_from_attr() {
+        let mut attr = PortAttr(ibverbs_sys::ibv_port_attr::default());
+        assert_eq!(attr.link_speed(), None);
+
+        attr.0.active_width = 2;
+        attr.0.active_speed = 32;
+        let speed = LinkSpeed {
+            width: LinkWidth::X4,
+            lane_speed: LaneSpeed::Edr,
+        };
+        assert_eq!(attr.link_speed(), Some(speed));
+    }
+}
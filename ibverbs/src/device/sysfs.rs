@@ -0,0 +1,219 @@
+use std::path::{Path, PathBuf};
+use std::{ffi, fs, io};
+
+/// The default mount point of sysfs
+pub const SYSFS_ROOT: &str = "/sys";
+
+/// The sysfs metadata of a RDMA device
+///
+/// The files are read from `<root>/class/infiniband/<name>`,
+/// where the root is `/sys` by default.
+#[derive(Debug, Clone)]
+pub struct DeviceSysfs {
+    path: PathBuf,
+}
+
+impl DeviceSysfs {
+    /// Locates the device `name` under the default sysfs root.
+    #[inline]
+    #[must_use]
+    pub fn new(name: impl AsRef<ffi::OsStr>) -> Self {
+        Self::with_root(SYSFS_ROOT, name)
+    }
+
+    /// Locates the device `name` under the sysfs root `root`.
+    #[inline]
+    #[must_use]
+    pub fn with_root(root: impl AsRef<Path>, name: impl AsRef<ffi::OsStr>) -> Self {
+        let path = root.as_ref().join("class/infiniband").join(name.as_ref());
+        Self { path }
+    }
+
+    /// Returns the directory of the device.
+    #[inline]
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the names of the network interfaces of the device,
+    /// which is empty if there is none.
+    #[inline]
+    pub fn netdevs(&self) -> io::Result<Vec<String>> {
+        let dir = match fs::read_dir(self.path.join("device/net")) {
+            Ok(dir) => dir,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let mut names = Vec::new();
+        for entry in dir {
+            let name = entry?.file_name();
+            if let Some(name) = name.to_str() {
+                names.push(name.to_owned());
+            }
+        }
+        names.sort_unstable();
+        Ok(names)
+    }
+
+    /// Returns the NUMA node of the device.
+    ///
+    /// Returns `None` if the device is not bound to a NUMA node.
+    #[inline]
+    pub fn numa_node(&self) -> io::Result<Option<u32>> {
+        let Some(content) = read_optional(&self.path.join("device/numa_node"))? else {
+            return Ok(None);
+        };
+        let node: i32 = content
+            .trim()
+            .parse()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Ok(u32::try_from(node).ok())
+    }
+
+    /// Returns the PCI address of the device, such as `0000:03:00.0`.
+    ///
+    /// Returns `None` if the device is not a PCI device.
+    #[inline]
+    pub fn pci_address(&self) -> io::Result<Option<String>> {
+        let device = match fs::canonicalize(self.path.join("device")) {
+            Ok(device) => device,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let is_pci = fs::canonicalize(device.join("subsystem"))
+            .is_ok_and(|subsystem| subsystem.ends_with("bus/pci"));
+        if !is_pci {
+            return Ok(None);
+        }
+        Ok(device
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(str::to_owned))
+    }
+}
+
+/// Reads a file which may not exist.
+fn read_optional(path: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::fs::symlink;
+    use std::{env, process};
+
+    /// A directory which is removed when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = env::temp_dir().join(format!("ibverbs-sysfs-{}-{name}", process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+
+        fn path(&self) -> &Path {
+            &self.0
+        }
+
+        fn device(&self, name: &str) -> PathBuf {
+            let path = self.0.join("class/infiniband").join(name);
+            fs::create_dir_all(&path).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn netdevs() {
+        let root = TempDir::new("netdevs");
+
+        let dev = root.device("mlx5_0");
+        fs::create_dir_all(dev.join("device/net/eth1")).unwrap();
+        fs::create_dir_all(dev.join("device/net/eth0")).unwrap();
+        let sysfs = DeviceSysfs::with_root(root.path(), "mlx5_0");
+        assert_eq!(sysfs.netdevs().unwrap(), ["eth0", "eth1"]);
+
+        let dev = root.device("mlx5_1");
+        fs::create_dir_all(dev.join("device/net")).unwrap();
+        let sysfs = DeviceSysfs::with_root(root.path(), "mlx5_1");
+        assert!(sysfs.netdevs().unwrap().is_empty());
+
+        root.device("ib0");
+        let sysfs = DeviceSysfs::with_root(root.path(), "ib0");
+        assert!(sysfs.netdevs().unwrap().is_empty());
+    }
+
+    #[test]
+    fn non_utf8_name() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let root = TempDir::new("non_utf8_name");
+        let name = ffi::OsStr::from_bytes(b"mlx5_\xff");
+        let dev = root.path().join("class/infiniband").join(name);
+        fs::create_dir_all(dev.join("device/net/eth0")).unwrap();
+        let sysfs = DeviceSysfs::with_root(root.path(), name);
+        assert_eq!(sysfs.path(), dev);
+        assert_eq!(sysfs.netdevs().unwrap(), ["eth0"]);
+    }
+
+    #[test]
+    fn numa_node() {
+        let root = TempDir::new("numa_node");
+        let read = |name: &str, content: Option<&str>| {
+            let dev = root.device(name);
+            if let Some(content) = content {
+                fs::create_dir_all(dev.join("device")).unwrap();
+                fs::write(dev.join("device/numa_node"), content).unwrap();
+            }
+            DeviceSysfs::with_root(root.path(), name).numa_node()
+        };
+
+        assert_eq!(read("node1", Some("1\n")).unwrap(), Some(1));
+        assert_eq!(read("unbound", Some("-1\n")).unwrap(), None);
+        assert_eq!(read("missing", None).unwrap(), None);
+        let err = read("invalid", Some("node0\n")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn pci_address() {
+        let root = TempDir::new("pci_address");
+        fs::create_dir_all(root.path().join("bus/pci")).unwrap();
+        fs::create_dir_all(root.path().join("bus/platform")).unwrap();
+
+        let pci = root.path().join("devices/pci0000:00/0000:03:00.0");
+        fs::create_dir_all(&pci).unwrap();
+        symlink(root.path().join("bus/pci"), pci.join("subsystem")).unwrap();
+        symlink(&pci, root.device("mlx5_0").join("device")).unwrap();
+        let sysfs = DeviceSysfs::with_root(root.path(), "mlx5_0");
+        assert_eq!(
+            sysfs.pci_address().unwrap().as_deref(),
+            Some("0000:03:00.0")
+        );
+
+        let platform = root.path().join("devices/platform/hns-0");
+        fs::create_dir_all(&platform).unwrap();
+        symlink(root.path().join("bus/platform"), platform.join("subsystem")).unwrap();
+        symlink(&platform, root.device("hns_0").join("device")).unwrap();
+        let sysfs = DeviceSysfs::with_root(root.path(), "hns_0");
+        assert_eq!(sysfs.pci_address().unwrap(), None);
+
+        root.device("rxe0");
+        let sysfs = DeviceSysfs::with_root(root.path(), "rxe0");
+        assert_eq!(sysfs.pci_address().unwrap(), None);
+    }
+}
@@ -1,8 +1,10 @@
 use crate::ctx::Context;
-use crate::error::create_resource;
+use crate::error::{create_resource, custom_error, from_errno};
 
 use std::{io, mem, ptr, sync};
 
+use numeric_cast::NumericCast;
+
 #[derive(Clone)]
 pub struct DeviceMemory(sync::Arc<Owner>);
 
@@ -24,15 +26,68 @@ impl DeviceMemory {
             )?;
             sync::Arc::new(Owner {
                 dm,
+                length: attr.length,
                 _ctx: ctx.clone(),
             })
         };
         Ok(Self(owner))
     }
+
+    pub(crate) fn ffi_ptr(&self) -> *mut ibverbs_sys::ibv_dm {
+        self.0.ffi_ptr()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn length(&self) -> usize {
+        self.0.length
+    }
+
+    fn check_bounds(&self, offset: usize, len: usize) -> io::Result<()> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.length() => Ok(()),
+            _ => Err(custom_error("the range is out of device memory bounds")),
+        }
+    }
+
+    /// Copies `src` into the device memory at `offset`.
+    #[inline]
+    pub fn copy_to_device(&self, offset: usize, src: &[u8]) -> io::Result<()> {
+        self.check_bounds(offset, src.len())?;
+        // SAFETY: ffi
+        unsafe {
+            let host_addr = src.as_ptr().cast();
+            let dm_offset = offset.numeric_cast();
+            let ret =
+                ibverbs_sys::ibv_memcpy_to_dm(self.ffi_ptr(), dm_offset, host_addr, src.len());
+            if ret != 0 {
+                return Err(from_errno(ret));
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the device memory at `offset` into `dst`.
+    #[inline]
+    pub fn copy_from_device(&self, offset: usize, dst: &mut [u8]) -> io::Result<()> {
+        self.check_bounds(offset, dst.len())?;
+        // SAFETY: ffi
+        unsafe {
+            let host_addr = dst.as_mut_ptr().cast();
+            let dm_offset = offset.numeric_cast();
+            let ret =
+                ibverbs_sys::ibv_memcpy_from_dm(host_addr, self.ffi_ptr(), dm_offset, dst.len());
+            if ret != 0 {
+                return Err(from_errno(ret));
+            }
+        }
+        Ok(())
+    }
 }
 
 struct Owner {
     dm: ptr::NonNull<ibverbs_sys::ibv_dm>,
+    length: usize,
     _ctx: Context,
 }
 
@@ -71,3 +126,22 @@ impl Default for DeviceMemoryOptions {
         }
     }
 }
+
+impl DeviceMemoryOptions {
+    #[inline]
+    pub fn length(&mut self, length: usize) -> &mut Self {
+        self.attr.length = length;
+        self
+    }
+
+    /// Sets the required alignment in bytes.
+    ///
+    /// # Panics
+    /// + if `align` is not a power of two
+    #[inline]
+    pub fn alignment(&mut self, align: usize) -> &mut Self {
+        assert!(align.is_power_of_two(), "alignment must be a power of two");
+        self.attr.log_align_req = align.trailing_zeros();
+        self
+    }
+}
@@ -0,0 +1,616 @@
+//! Future-based completion of work requests.
+//!
+//! A [`CompletionDriver`] owns the `wr_id`s of the work requests posted through it
+//! and resolves a [`WorkRequestFuture`] for each of them when its completion is polled.
+
+use crate::cq::CompletionQueue;
+use crate::error::custom_error;
+use crate::qp::QueuePair;
+use crate::wc::{Opcode, WorkCompletion, WorkCompletionError, WorkCompletionFlags};
+use crate::wr::{RecvRequest, SendRequest};
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{self, AtomicBool, AtomicU64};
+use std::task::{Context, Poll, Waker};
+use std::{error, fmt, io, mem, sync, thread};
+
+use fnv::FnvHashMap;
+
+/// The information of a successful work completion
+#[derive(Debug, Clone, Copy)]
+pub struct WorkCompletionInfo {
+    opcode: Opcode,
+    byte_len: u32,
+    qp_num: u32,
+    wc_flags: WorkCompletionFlags,
+    imm_data: Option<u32>,
+}
+
+impl WorkCompletionInfo {
+    fn new(wc: &WorkCompletion) -> Self {
+        Self {
+            opcode: wc.opcode(),
+            byte_len: wc.byte_len(),
+            qp_num: wc.qp_num(),
+            wc_flags: wc.wc_flags(),
+            imm_data: wc.imm_data(),
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn opcode(&self) -> Opcode {
+        self.opcode
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn byte_len(&self) -> u32 {
+        self.byte_len
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn qp_num(&self) -> u32 {
+        self.qp_num
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn wc_flags(&self) -> WorkCompletionFlags {
+        self.wc_flags
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn imm_data(&self) -> Option<u32> {
+        self.imm_data
+    }
+}
+
+/// The error of a work request posted by [`CompletionDriver`]
+#[derive(Debug)]
+pub enum CompletionError {
+    /// The work request completed with an error status
+    WorkCompletion(WorkCompletionError),
+    /// Polling the completion queue failed, so the completion is lost
+    Poll(io::Error),
+}
+
+impl fmt::Display for CompletionError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WorkCompletion(err) => write!(f, "work completion error: {err}"),
+            Self::Poll(err) => write!(f, "failed to poll the completion queue: {err}"),
+        }
+    }
+}
+
+impl error::Error for CompletionError {
+    #[inline]
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::WorkCompletion(err) => Some(err),
+            Self::Poll(err) => Some(err),
+        }
+    }
+}
+
+impl From<WorkCompletionError> for CompletionError {
+    #[inline]
+    fn from(err: WorkCompletionError) -> Self {
+        Self::WorkCompletion(err)
+    }
+}
+
+type CompletionResult = Result<WorkCompletionInfo, CompletionError>;
+
+enum Slot {
+    Pending(Option<Waker>),
+    Ready(CompletionResult),
+    /// The future is dropped before the completion arrives.
+    Abandoned,
+}
+
+/// The slots of the posted work requests
+#[derive(Default)]
+struct Slots {
+    map: FnvHashMap<u64, Slot>,
+    /// The polling error which has stopped the driver
+    failed: Option<io::Error>,
+}
+
+impl Slots {
+    /// Adds a pending slot, which fails if the driver has stopped.
+    fn insert(&mut self, id: u64) -> io::Result<()> {
+        if let Some(ref err) = self.failed {
+            return Err(copy_error(err));
+        }
+        self.map.insert(id, Slot::Pending(None));
+        Ok(())
+    }
+
+    /// Stores the result of the work request `id` and returns the waker of its future.
+    ///
+    /// Completions of work requests posted in other ways are skipped.
+    fn complete(&mut self, id: u64, result: CompletionResult) -> Option<Waker> {
+        let slot = self.map.get_mut(&id)?;
+        match mem::replace(slot, Slot::Ready(result)) {
+            Slot::Pending(waker) => waker,
+            Slot::Abandoned => {
+                self.map.remove(&id);
+                None
+            }
+            Slot::Ready(_) => None,
+        }
+    }
+
+    /// Completes all pending slots with the polling error `err`,
+    /// rejects new work requests, and returns the wakers of the futures.
+    fn fail(&mut self, err: io::Error) -> Vec<Waker> {
+        let mut wakers = Vec::new();
+        for slot in self.map.values_mut() {
+            if let Slot::Pending(waker) = slot {
+                wakers.extend(waker.take());
+                *slot = Slot::Ready(Err(CompletionError::Poll(copy_error(&err))));
+            }
+        }
+        self.failed.get_or_insert(err);
+        wakers
+    }
+
+    /// Takes the result of the work request `id`, or stores `waker` if it is pending.
+    fn poll(&mut self, id: u64, waker: &Waker) -> Poll<CompletionResult> {
+        match self.map.get_mut(&id) {
+            Some(Slot::Pending(slot)) => {
+                if !slot.as_ref().is_some_and(|w| w.will_wake(waker)) {
+                    *slot = Some(waker.clone());
+                }
+                Poll::Pending
+            }
+            Some(Slot::Ready(_)) => match self.map.remove(&id) {
+                Some(Slot::Ready(result)) => Poll::Ready(result),
+                _ => unreachable!(),
+            },
+            Some(Slot::Abandoned) | None => unreachable!(),
+        }
+    }
+
+    /// Releases the slot of a dropped future.
+    ///
+    /// A pending slot is kept until the completion arrives,
+    /// which then removes it.
+    fn abandon(&mut self, id: u64) {
+        if matches!(self.map.get(&id), Some(Slot::Pending(_))) {
+            self.map.insert(id, Slot::Abandoned);
+        } else {
+            self.map.remove(&id);
+        }
+    }
+}
+
+fn wc_result(wc: &WorkCompletion) -> CompletionResult {
+    WorkCompletionError::result(wc.status())
+        .map(|()| WorkCompletionInfo::new(wc))
+        .map_err(CompletionError::from)
+}
+
+/// Copies an error which is reported to more than one receiver.
+fn copy_error(err: &io::Error) -> io::Error {
+    io::Error::new(err.kind(), err.to_string())
+}
+
+/// A driver which allocates `wr_id`s and completes the futures of posted work requests.
+///
+/// The completion queue is polled by [`CompletionDriver::poll`],
+/// by a background poller ([`CompletionDriver::spawn_poller`]),
+/// or by the futures themselves in cooperative mode ([`CompletionDriver::cooperative`]).
+///
+/// Completions with `wr_id`s unknown to the driver are discarded,
+/// so work requests posted in other ways on the same completion queue are not reported.
+///
+/// Once the background poller or a cooperative future fails to poll,
+/// the pending futures complete with [`CompletionError::Poll`]
+/// and new work requests are rejected with the same error.
+#[derive(Clone)]
+pub struct CompletionDriver(sync::Arc<Inner>);
+
+struct Inner {
+    cq: CompletionQueue,
+    next_id: AtomicU64,
+    cooperative: bool,
+    slots: sync::Mutex<Slots>,
+    buf: sync::Mutex<Vec<mem::MaybeUninit<WorkCompletion>>>,
+}
+
+impl CompletionDriver {
+    /// Creates a driver which is polled by [`CompletionDriver::poll`] or a background poller.
+    #[inline]
+    #[must_use]
+    pub fn new(cq: CompletionQueue) -> Self {
+        Self::with_mode(cq, false)
+    }
+
+    /// Creates a driver whose futures poll the completion queue by themselves.
+    ///
+    /// A pending future wakes itself immediately,
+    /// which busy-polls the completion queue on the executor.
+    #[inline]
+    #[must_use]
+    pub fn cooperative(cq: CompletionQueue) -> Self {
+        Self::with_mode(cq, true)
+    }
+
+    fn with_mode(cq: CompletionQueue, cooperative: bool) -> Self {
+        Self(sync::Arc::new(Inner {
+            cq,
+            next_id: AtomicU64::new(0),
+            cooperative,
+            slots: sync::Mutex::new(Slots::default()),
+            buf: sync::Mutex::new(vec![mem::MaybeUninit::uninit(); 32]),
+        }))
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn cq(&self) -> &CompletionQueue {
+        &self.0.cq
+    }
+
+    /// Posts a send request and returns the future of its completion.
+    ///
+    /// The `wr_id` of the request is overwritten and the request is always signaled.
+    ///
+    /// # Safety
+    /// + the memory referenced by the request must be valid until the work request completes,
+    ///   even if the future is dropped
+    /// + the send queue of `qp` must be associated with the completion queue of the driver
+    #[inline]
+    pub unsafe fn post_send(
+        &self,
+        qp: &QueuePair,
+        wr: &mut SendRequest,
+    ) -> io::Result<WorkRequestFuture> {
+        if wr.is_chained() {
+            return Err(custom_error("chained work requests are not supported"));
+        }
+        let id = self.0.register()?;
+        wr.id(id).set_signaled();
+        // SAFETY: guaranteed by caller
+        let ret = unsafe { qp.post_send(wr) };
+        self.0.finish_post(id, ret)
+    }
+
+    /// Posts a receive request and returns the future of its completion.
+    ///
+    /// The `wr_id` of the request is overwritten.
+    ///
+    /// # Safety
+    /// + the memory referenced by the request must be valid until the work request completes,
+    ///   even if the future is dropped
+    /// + the receive queue of `qp` must be associated with the completion queue of the driver
+    #[inline]
+    pub unsafe fn post_recv(
+        &self,
+        qp: &QueuePair,
+        wr: &mut RecvRequest,
+    ) -> io::Result<WorkRequestFuture> {
+        if wr.is_chained() {
+            return Err(custom_error("chained work requests are not supported"));
+        }
+        let id = self.0.register()?;
+        wr.id(id);
+        // SAFETY: guaranteed by caller
+        let ret = unsafe { qp.post_recv(wr) };
+        self.0.finish_post(id, ret)
+    }
+
+    /// Polls the completion queue once and completes the corresponding futures.
+    ///
+    /// Returns the number of polled completions.
+    /// Returns zero if another thread is polling.
+    #[inline]
+    pub fn poll(&self) -> io::Result<usize> {
+        self.0.progress()
+    }
+
+    /// Spawns a thread which polls the completion queue until the handle is dropped.
+    ///
+    /// If polling fails, the poller completes all pending futures with
+    /// [`CompletionError::Poll`] and stops, and the error is returned by [`PollerHandle::stop`].
+    #[inline]
+    pub fn spawn_poller(&self) -> io::Result<PollerHandle> {
+        let stop = sync::Arc::new(AtomicBool::new(false));
+        let thread = {
+            let inner = sync::Arc::clone(&self.0);
+            let stop = sync::Arc::clone(&stop);
+            thread::Builder::new()
+                .name("ibverbs-cq-poller".into())
+                .spawn(move || -> io::Result<()> {
+                    while !stop.load(atomic::Ordering::Acquire) {
+                        match inner.progress() {
+                            Ok(0) => thread::yield_now(),
+                            Ok(_) => {}
+                            Err(err) => {
+                                inner.fail(copy_error(&err));
+                                return Err(err);
+                            }
+                        }
+                    }
+                    Ok(())
+                })?
+        };
+        Ok(PollerHandle {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Inner {
+    fn register(&self) -> io::Result<u64> {
+        let id = self.next_id.fetch_add(1, atomic::Ordering::Relaxed);
+        self.slots.lock().unwrap().insert(id)?;
+        Ok(id)
+    }
+
+    fn finish_post(
+        self: &sync::Arc<Self>,
+        id: u64,
+        ret: io::Result<()>,
+    ) -> io::Result<WorkRequestFuture> {
+        if let Err(err) = ret {
+            self.slots.lock().unwrap().map.remove(&id);
+            return Err(err);
+        }
+        Ok(WorkRequestFuture {
+            inner: sync::Arc::clone(self),
+            id,
+            done: false,
+        })
+    }
+
+    fn progress(&self) -> io::Result<usize> {
+        let Ok(mut buf) = self.buf.try_lock() else {
+            return Ok(0);
+        };
+        let wcs = self.cq.poll(&mut buf)?;
+        if wcs.is_empty() {
+            return Ok(0);
+        }
+
+        let mut wakers = Vec::with_capacity(wcs.len());
+        {
+            let mut slots = self.slots.lock().unwrap();
+            for wc in &*wcs {
+                wakers.extend(slots.complete(wc.wr_id(), wc_result(wc)));
+            }
+        }
+        wakers.into_iter().for_each(Waker::wake);
+
+        Ok(wcs.len())
+    }
+
+    /// Completes all pending futures with the polling error `err` and stops the driver.
+    fn fail(&self, err: io::Error) {
+        let wakers = self.slots.lock().unwrap().fail(err);
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+/// The future of a work request posted by [`CompletionDriver`]
+///
+/// Dropping the future before completion discards the completion,
+/// but does not cancel the work request, whose memory must stay valid until it completes.
+pub struct WorkRequestFuture {
+    inner: sync::Arc<Inner>,
+    id: u64,
+    done: bool,
+}
+
+impl WorkRequestFuture {
+    #[inline]
+    #[must_use]
+    pub fn wr_id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl Future for WorkRequestFuture {
+    type Output = CompletionResult;
+
+    #[inline]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        assert!(!self.done, "the future is polled after completion");
+
+        if self.inner.cooperative
+            && let Err(err) = self.inner.progress()
+        {
+            self.inner.fail(err);
+        }
+
+        let ret = self.inner.slots.lock().unwrap().poll(self.id, cx.waker());
+        match ret {
+            Poll::Ready(_) => self.done = true,
+            Poll::Pending if self.inner.cooperative => cx.waker().wake_by_ref(),
+            Poll::Pending => {}
+        }
+        ret
+    }
+}
+
+impl Drop for WorkRequestFuture {
+    #[inline]
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        self.inner.slots.lock().unwrap().abandon(self.id);
+    }
+}
+
+/// The handle of a background poller
+///
+/// The poller is stopped and joined when the handle is dropped.
+pub struct PollerHandle {
+    stop: sync::Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<io::Result<()>>>,
+}
+
+impl PollerHandle {
+    /// Stops the poller and returns the polling error if any.
+    ///
+    /// Returns an error if the poller has panicked.
+    #[inline]
+    pub fn stop(mut self) -> io::Result<()> {
+        self.join()
+    }
+
+    fn join(&mut self) -> io::Result<()> {
+        self.stop.store(true, atomic::Ordering::Release);
+        match self.thread.take() {
+            Some(thread) => thread
+                .join()
+                .unwrap_or_else(|_| Err(custom_error("the poller panicked"))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for PollerHandle {
+    #[inline]
+    fn drop(&mut self) {
+        let _ = self.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ibverbs_sys::ibv_wc_status;
+    use std::sync::atomic::AtomicUsize;
+    use std::task::Wake;
+
+    fn wc(wr_id: u64, status: u32) -> WorkCompletion {
+        // SAFETY: POD ffi type
+        let mut raw: ibverbs_sys::ibv_wc = unsafe { mem::zeroed() };
+        raw.wr_id = wr_id;
+        raw.status = status;
+        raw.byte_len = 64;
+        // SAFETY: transparent wrapper
+        unsafe { mem::transmute(raw) }
+    }
+
+    fn complete(slots: &mut Slots, wr_id: u64, status: u32) -> Option<Waker> {
+        slots.complete(wr_id, wc_result(&wc(wr_id, status)))
+    }
+
+    struct CountWaker(AtomicUsize);
+
+    impl Wake for CountWaker {
+        fn wake(self: sync::Arc<Self>) {
+            self.0.fetch_add(1, atomic::Ordering::Relaxed);
+        }
+    }
+
+    fn count_waker() -> (sync::Arc<CountWaker>, Waker) {
+        let counter = sync::Arc::new(CountWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(sync::Arc::clone(&counter));
+        (counter, waker)
+    }
+
+    fn woken(counter: &CountWaker) -> usize {
+        counter.0.load(atomic::Ordering::Relaxed)
+    }
+
+    #[test]
+    fn pending_to_ready() {
+        let (counter, waker) = count_waker();
+        let mut slots = Slots::default();
+        slots.insert(1).unwrap();
+        assert!(slots.poll(1, &waker).is_pending());
+
+        complete(&mut slots, 1, ibv_wc_status::IBV_WC_SUCCESS)
+            .unwrap()
+            .wake();
+        assert_eq!(woken(&counter), 1);
+
+        let Poll::Ready(Ok(info)) = slots.poll(1, &waker) else {
+            panic!("the slot is not ready");
+        };
+        assert_eq!(info.byte_len(), 64);
+        assert!(slots.map.is_empty());
+    }
+
+    #[test]
+    fn ready_before_poll() {
+        let (counter, waker) = count_waker();
+        let mut slots = Slots::default();
+        slots.insert(1).unwrap();
+        assert!(complete(&mut slots, 1, ibv_wc_status::IBV_WC_REM_ACCESS_ERR).is_none());
+
+        let ret = slots.poll(1, &waker);
+        assert!(matches!(
+            ret,
+            Poll::Ready(Err(CompletionError::WorkCompletion(
+                WorkCompletionError::RemoteAccess
+            )))
+        ));
+        assert_eq!(woken(&counter), 0);
+        assert!(slots.map.is_empty());
+    }
+
+    #[test]
+    fn abandoned() {
+        let mut slots = Slots::default();
+        slots.insert(1).unwrap();
+        slots.abandon(1);
+        assert!(matches!(slots.map.get(&1), Some(Slot::Abandoned)));
+        assert!(complete(&mut slots, 1, ibv_wc_status::IBV_WC_SUCCESS).is_none());
+        assert!(slots.map.is_empty());
+
+        slots.insert(2).unwrap();
+        assert!(complete(&mut slots, 2, ibv_wc_status::IBV_WC_SUCCESS).is_none());
+        slots.abandon(2);
+        assert!(slots.map.is_empty());
+    }
+
+    #[test]
+    fn unknown_wr_id() {
+        let mut slots = Slots::default();
+        slots.insert(1).unwrap();
+        assert!(complete(&mut slots, 7, ibv_wc_status::IBV_WC_SUCCESS).is_none());
+        assert!(matches!(slots.map.get(&1), Some(Slot::Pending(None))));
+        assert_eq!(slots.map.len(), 1);
+    }
+
+    #[test]
+    fn fail() {
+        let (counter, waker) = count_waker();
+        let mut slots = Slots::default();
+        slots.insert(1).unwrap();
+        slots.insert(2).unwrap();
+        slots.insert(3).unwrap();
+        assert!(slots.poll(1, &waker).is_pending());
+        assert!(complete(&mut slots, 2, ibv_wc_status::IBV_WC_SUCCESS).is_none());
+        slots.abandon(3);
+
+        let err = io::Error::from(io::ErrorKind::BrokenPipe);
+        slots.fail(err).into_iter().for_each(Waker::wake);
+        assert_eq!(woken(&counter), 1);
+
+        let Poll::Ready(Err(CompletionError::Poll(err))) = slots.poll(1, &waker) else {
+            panic!("the slot is not failed");
+        };
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        assert!(matches!(slots.poll(2, &waker), Poll::Ready(Ok(_))));
+        assert!(matches!(slots.map.get(&3), Some(Slot::Abandoned)));
+
+        let err = slots.insert(4).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        assert!(!slots.map.contains_key(&4));
+    }
+}
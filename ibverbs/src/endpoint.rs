@@ -0,0 +1,252 @@
+use crate::buf::RegisteredBuffer;
+use crate::device::{Gid, Mtu};
+use crate::error::custom_error;
+use crate::mr::{MemoryRegion, OwnedMemoryRegion};
+use crate::qp::{PathAttr, RemoteEndpoint, RtrAttr};
+
+use std::io;
+
+use numeric_cast::NumericCast;
+
+/// A queue pair endpoint which is exchanged out of band to set up a connection.
+///
+/// The binary encoding is fixed and uses network byte order for integers.
+/// The GID is encoded as its raw bytes, which are already in network byte order.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConnectionInfo {
+    pub qp_num: u32,
+    pub psn: u32,
+    pub lid: u16,
+    pub gid: Gid,
+    pub port_num: u8,
+    pub mtu: Mtu,
+    pub buffers: Vec<RemoteBuffer>,
+}
+
+/// A memory region which is advertised to the remote side
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RemoteBuffer {
+    pub addr: u64,
+    pub rkey: u32,
+    pub length: u64,
+}
+
+impl<T> From<&MemoryRegion<T>> for RemoteBuffer {
+    #[inline]
+    fn from(mr: &MemoryRegion<T>) -> Self {
+        Self {
+            addr: mr.iova(),
+            rkey: mr.rkey(),
+            length: mr.length().numeric_cast(),
+        }
+    }
+}
+
+impl<B: RegisteredBuffer> From<&OwnedMemoryRegion<B>> for RemoteBuffer {
+    #[inline]
+    fn from(mr: &OwnedMemoryRegion<B>) -> Self {
+        Self {
+            addr: mr.addr_u64(),
+            rkey: mr.rkey(),
+            length: mr.length().numeric_cast(),
+        }
+    }
+}
+
+impl ConnectionInfo {
+    /// The encoded length without buffers
+    pub const HEADER_LEN: usize = 30;
+
+    /// The encoded length of each buffer
+    pub const BUFFER_LEN: usize = 20;
+
+    #[inline]
+    #[must_use]
+    pub fn encoded_len(&self) -> usize {
+        Self::HEADER_LEN + Self::BUFFER_LEN * self.buffers.len()
+    }
+
+    /// Appends the binary encoding to `buf`.
+    ///
+    /// # Panics
+    /// + if there are more than `u16::MAX` buffers
+    #[inline]
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        let num_buffers = u16::try_from(self.buffers.len()).expect("too many buffers");
+        buf.reserve(self.encoded_len());
+        buf.extend_from_slice(&self.qp_num.to_be_bytes());
+        buf.extend_from_slice(&self.psn.to_be_bytes());
+        buf.extend_from_slice(&self.lid.to_be_bytes());
+        buf.extend_from_slice(self.gid.as_bytes());
+        buf.push(self.port_num);
+        buf.push(self.mtu as u8);
+        buf.extend_from_slice(&num_buffers.to_be_bytes());
+        for buffer in &self.buffers {
+            buf.extend_from_slice(&buffer.addr.to_be_bytes());
+            buf.extend_from_slice(&buffer.rkey.to_be_bytes());
+            buf.extend_from_slice(&buffer.length.to_be_bytes());
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.encode(&mut buf);
+        buf
+    }
+
+    /// Decodes the binary encoding which must span the whole `bytes`.
+    #[inline]
+    pub fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader(bytes);
+        let qp_num = u32::from_be_bytes(reader.read()?);
+        let psn = u32::from_be_bytes(reader.read()?);
+        let lid = u16::from_be_bytes(reader.read()?);
+        let gid = Gid::from_bytes(reader.read()?);
+        let [port_num] = reader.read()?;
+        let [mtu] = reader.read()?;
+        let mtu = Mtu::try_from(u32::from(mtu)).map_err(|()| custom_error("invalid mtu"))?;
+        let num_buffers = u16::from_be_bytes(reader.read()?);
+        let buffers = (0..num_buffers)
+            .map(|_| {
+                Ok(RemoteBuffer {
+                    addr: u64::from_be_bytes(reader.read()?),
+                    rkey: u32::from_be_bytes(reader.read()?),
+                    length: u64::from_be_bytes(reader.read()?),
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        if !reader.0.is_empty() {
+            return Err(custom_error("trailing bytes in connection info"));
+        }
+        Ok(Self {
+            qp_num,
+            psn,
+            lid,
+            gid,
+            port_num,
+            mtu,
+            buffers,
+        })
+    }
+
+    /// Returns the remote endpoint for the RTR transition.
+    ///
+    /// A zero GID is treated as absent.
+    #[inline]
+    #[must_use]
+    pub fn remote_endpoint(&self) -> RemoteEndpoint {
+        let has_gid = self.gid.as_bytes().iter().any(|&b| b != 0);
+        RemoteEndpoint {
+            qp_num: self.qp_num,
+            psn: self.psn,
+            lid: self.lid,
+            gid: has_gid.then_some(self.gid),
+        }
+    }
+
+    /// Returns the RTR attributes of an RC queue pair towards this endpoint.
+    ///
+    /// The path MTU is the smaller one of `path.mtu` and the remote MTU.
+    /// For UC queue pairs, `max_dest_rd_atomic` and `min_rnr_timer` must be cleared.
+    #[inline]
+    #[must_use]
+    pub fn rtr_attr(
+        &self,
+        mut path: PathAttr,
+        max_dest_rd_atomic: u8,
+        min_rnr_timer: u8,
+    ) -> RtrAttr {
+        path.mtu = path.mtu.min(self.mtu);
+        RtrAttr {
+            remote: Some(self.remote_endpoint()),
+            path: Some(path),
+            max_dest_rd_atomic: Some(max_dest_rd_atomic),
+            min_rnr_timer: Some(min_rnr_timer),
+        }
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn read<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let Some((head, tail)) = self.0.split_first_chunk::<N>() else {
+            return Err(custom_error("truncated connection info"));
+        };
+        self.0 = tail;
+        Ok(*head)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> ConnectionInfo {
+        ConnectionInfo {
+            qp_num: 0x0001_0203,
+            psn: 0x00a0_b0c0,
+            lid: 0x1234,
+            gid: Gid::from_bytes([
+                0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0x02, 0x02, 0xc9, 0xff, 0xfe, 0, 0, 0x01,
+            ]),
+            port_num: 1,
+            mtu: Mtu::Mtu1024,
+            buffers: vec![RemoteBuffer {
+                addr: 0x1122_3344_5566_7788,
+                rkey: 0xdead_beef,
+                length: 0x1000,
+            }],
+        }
+    }
+
+    #[test]
+    fn byte_order() {
+        let bytes = sample().to_bytes();
+        let expected: &[u8] = &[
+            0x00, 0x01, 0x02, 0x03, // qp_num
+            0x00, 0xa0, 0xb0, 0xc0, // psn
+            0x12, 0x34, // lid
+            0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0x02, 0x02, 0xc9, 0xff, 0xfe, 0, 0, 0x01, // gid
+            0x01, // port_num
+            0x03, // mtu
+            0x00, 0x01, // number of buffers
+            0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, // addr
+            0xde, 0xad, 0xbe, 0xef, // rkey
+            0, 0, 0, 0, 0, 0, 0x10, 0x00, // length
+        ];
+        assert_eq!(bytes, expected);
+        assert_eq!(bytes.len(), sample().encoded_len());
+    }
+
+    #[test]
+    fn round_trip() {
+        let info = sample();
+        let decoded = ConnectionInfo::decode(&info.to_bytes()).unwrap();
+        assert_eq!(decoded.qp_num, info.qp_num);
+        assert_eq!(decoded.psn, info.psn);
+        assert_eq!(decoded.lid, info.lid);
+        assert_eq!(decoded.gid, info.gid);
+        assert_eq!(decoded.port_num, info.port_num);
+        assert_eq!(decoded.mtu, info.mtu);
+        assert_eq!(decoded.buffers, info.buffers);
+    }
+
+    #[test]
+    fn malformed() {
+        let bytes = sample().to_bytes();
+        assert!(ConnectionInfo::decode(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(ConnectionInfo::decode(&trailing).is_err());
+
+        let mut bad_mtu = bytes;
+        bad_mtu[27] = 0;
+        assert!(ConnectionInfo::decode(&bad_mtu).is_err());
+    }
+}
@@ -58,17 +58,27 @@ pub mod device {
 
     mod guid;
     pub use self::guid::*;
+
+    mod sysfs;
+    pub use self::sysfs::*;
 }
 
 pub mod ah;
+pub mod buf;
 pub mod cc;
+pub mod clock;
+pub mod connect;
 pub mod cq;
 pub mod ctx;
 pub mod dm;
+pub mod driver;
+pub mod endpoint;
 pub mod mr;
 pub mod mw;
 pub mod pd;
+pub mod pool;
 pub mod qp;
 pub mod srq;
 pub mod wc;
 pub mod wr;
+pub mod xrcd;
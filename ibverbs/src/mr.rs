@@ -1,8 +1,14 @@
-use crate::error::create_resource;
+use crate::buf::RegisteredBuffer;
+use crate::dm::DeviceMemory;
+use crate::error::{create_resource, custom_error, from_errno};
 use crate::pd::ProtectionDomain;
 use crate::utils::ptr_to_addr;
+use crate::wr::Sge;
 
-use std::{ffi, io, ptr, sync};
+use std::ops::{Bound, Range, RangeBounds};
+use std::os::fd::{AsRawFd, BorrowedFd};
+use std::sync::atomic::{self, AtomicU64};
+use std::{ffi, io, mem, ptr, slice, sync};
 
 use ibverbs_sys::ibv_access_flags;
 use numeric_cast::NumericCast;
@@ -21,7 +27,6 @@ impl<T> MemoryRegion<T> {
     /// # Safety
     /// 1. the memory region must be valid until it is deregistered
     /// 2. the memory region must be initialized before it is read for the first time
-    #[allow(clippy::arc_with_non_send_sync)] // FIXME: false positive
     #[inline]
     pub unsafe fn register(
         pd: &ProtectionDomain,
@@ -30,20 +35,195 @@ impl<T> MemoryRegion<T> {
         access_flags: AccessFlags,
         metadata: T,
     ) -> io::Result<Self> {
-        let owner = {
+        let iova = ptr_to_addr(addr).numeric_cast();
+        // SAFETY: ffi
+        let mr = unsafe {
             let addr = addr.cast();
             let access_flags = access_flags.bits() as ffi::c_int;
-            let mr = create_resource(
+            create_resource(
                 || ibverbs_sys::ibv_reg_mr(pd.ffi_ptr(), addr, length, access_flags),
                 || "failed to register memory region",
-            )?;
-            sync::Arc::new(Owner {
-                mr,
-                metadata,
-                _pd: pd.clone(),
-            })
+            )?
+        };
+        Ok(Self::from_raw(pd, mr, iova, None, metadata))
+    }
+
+    /// Registers a memory region like [`MemoryRegion::register`],
+    /// which is accessed by the virtual address `iova` instead of `addr`.
+    ///
+    /// # Safety
+    /// 1. the memory region must be valid until it is deregistered
+    /// 2. the memory region must be initialized before it is read for the first time
+    #[inline]
+    pub unsafe fn register_iova(
+        pd: &ProtectionDomain,
+        addr: *mut u8,
+        length: usize,
+        iova: u64,
+        access_flags: AccessFlags,
+        metadata: T,
+    ) -> io::Result<Self> {
+        // SAFETY: ffi
+        let mr = unsafe {
+            let addr = addr.cast();
+            let access_flags = access_flags.to_c_uint();
+            create_resource(
+                || ibverbs_sys::ibv_reg_mr_iova2(pd.ffi_ptr(), addr, length, iova, access_flags),
+                || "failed to register memory region",
+            )?
+        };
+        Ok(Self::from_raw(pd, mr, iova, None, metadata))
+    }
+
+    /// Registers a memory region of `length` bytes at `offset` of the dma-buf `fd`,
+    /// which is accessed by the virtual address `iova`.
+    #[inline]
+    pub fn register_dmabuf(
+        pd: &ProtectionDomain,
+        fd: BorrowedFd<'_>,
+        offset: u64,
+        length: usize,
+        iova: u64,
+        access_flags: AccessFlags,
+        metadata: T,
+    ) -> io::Result<Self> {
+        // SAFETY: ffi
+        let mr = unsafe {
+            let fd = fd.as_raw_fd();
+            let access_flags = access_flags.bits() as ffi::c_int;
+            create_resource(
+                || {
+                    ibverbs_sys::ibv_reg_dmabuf_mr(
+                        pd.ffi_ptr(),
+                        offset,
+                        length,
+                        iova,
+                        fd,
+                        access_flags,
+                    )
+                },
+                || "failed to register dma-buf memory region",
+            )?
+        };
+        Ok(Self::from_raw(pd, mr, iova, None, metadata))
+    }
+
+    #[allow(clippy::arc_with_non_send_sync)] // FIXME: false positive
+    fn from_raw(
+        pd: &ProtectionDomain,
+        mr: ptr::NonNull<ibverbs_sys::ibv_mr>,
+        iova: u64,
+        dm: Option<DeviceMemory>,
+        metadata: T,
+    ) -> Self {
+        Self(sync::Arc::new(Owner {
+            mr,
+            iova: AtomicU64::new(iova),
+            metadata,
+            pd: sync::Mutex::new(pd.clone()),
+            _dm: dm,
+        }))
+    }
+
+    /// Registers `length` bytes at `offset` of the device memory `dm` as a memory region,
+    /// which is zero-based and keeps the device memory alive.
+    ///
+    /// [`AccessFlags::ZERO_BASED`] is always set.
+    #[inline]
+    pub fn register_dm(
+        pd: &ProtectionDomain,
+        dm: &DeviceMemory,
+        offset: usize,
+        length: usize,
+        access_flags: AccessFlags,
+        metadata: T,
+    ) -> io::Result<Self> {
+        if offset
+            .checked_add(length)
+            .is_none_or(|end| end > dm.length())
+        {
+            return Err(custom_error("the range is out of device memory bounds"));
+        }
+        // SAFETY: ffi
+        let mr = unsafe {
+            let dm_offset = offset.numeric_cast();
+            let access_flags = (access_flags | AccessFlags::ZERO_BASED).to_c_uint();
+            create_resource(
+                || {
+                    ibverbs_sys::ibv_reg_dm_mr(
+                        pd.ffi_ptr(),
+                        dm.ffi_ptr(),
+                        dm_offset,
+                        length,
+                        access_flags,
+                    )
+                },
+                || "failed to register device memory region",
+            )?
         };
-        Ok(Self(owner))
+        Ok(Self::from_raw(pd, mr, 0, Some(dm.clone()), metadata))
+    }
+
+    /// Registers an implicit on-demand paging memory region
+    /// which covers the whole address space of the process.
+    ///
+    /// [`AccessFlags::ON_DEMAND`] is always set.
+    /// The support can be checked by [`OdpGeneralCaps::SUPPORT_IMPLICIT`](crate::device::OdpGeneralCaps::SUPPORT_IMPLICIT).
+    ///
+    /// # Safety
+    /// the remote side can access any memory of the process that the access flags allow
+    #[inline]
+    pub unsafe fn register_implicit_odp(
+        pd: &ProtectionDomain,
+        access_flags: AccessFlags,
+        metadata: T,
+    ) -> io::Result<Self> {
+        let access_flags = access_flags | AccessFlags::ON_DEMAND;
+        // SAFETY: guaranteed by caller
+        unsafe { Self::register(pd, ptr::null_mut(), usize::MAX, access_flags, metadata) }
+    }
+
+    /// Gives advice about the address ranges of the memory region,
+    /// which are offsets relative to its starting address.
+    ///
+    /// `flush` makes the call synchronous for prefetching.
+    #[inline]
+    pub fn advise(&self, advice: AdviseMr, flush: bool, ranges: &[Range<usize>]) -> io::Result<()> {
+        let mut sg_list = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            if range.start > range.end || range.end > self.length() {
+                return Err(custom_error("the advice range is out of bounds"));
+            }
+            let Ok(length) = u32::try_from(range.end - range.start) else {
+                return Err(custom_error("the advice range is too long"));
+            };
+            sg_list.push(ibverbs_sys::ibv_sge {
+                addr: self.iova() + range.start.numeric_cast::<u64>(),
+                length,
+                lkey: self.lkey(),
+            });
+        }
+        let flags = if flush {
+            ibverbs_sys::IBV_ADVISE_MR_FLAG_FLUSH
+        } else {
+            0
+        };
+        // SAFETY: ffi
+        unsafe {
+            let pd = self.0.lock_pd().ffi_ptr();
+            let num_sge = sg_list.len().numeric_cast();
+            let ret = ibverbs_sys::ibv_advise_mr(
+                pd,
+                advice.to_raw(),
+                flags,
+                sg_list.as_mut_ptr(),
+                num_sge,
+            );
+            if ret != 0 {
+                return Err(from_errno(ret));
+            }
+        }
+        Ok(())
     }
 
     #[inline]
@@ -78,6 +258,79 @@ impl<T> MemoryRegion<T> {
         unsafe { ptr_to_addr((*mr).addr) }.numeric_cast()
     }
 
+    /// Re-registers the memory region in place.
+    ///
+    /// The held protection domain is replaced if it is changed.
+    /// If it fails with a kind other than [`io::ErrorKind::InvalidInput`],
+    /// the memory region may be left in an unusable state and should be dropped.
+    ///
+    /// # Safety
+    /// 1. the memory region must not be used by other threads or pending work requests
+    /// 2. the new translation must satisfy the requirements of [`MemoryRegion::register`]
+    #[inline]
+    pub unsafe fn rereg(&self, options: ReregOptions) -> io::Result<ReregReport> {
+        let (old_lkey, old_rkey) = (self.lkey(), self.rkey());
+
+        let mut flags = 0;
+        let (addr, length) = match options.translation {
+            Some((addr, length)) => {
+                flags |= ibverbs_sys::IBV_REREG_MR_CHANGE_TRANSLATION;
+                (addr.cast(), length)
+            }
+            None => (ptr::null_mut(), 0),
+        };
+        let pd = match options.pd {
+            Some(ref pd) => {
+                flags |= ibverbs_sys::IBV_REREG_MR_CHANGE_PD;
+                pd.ffi_ptr()
+            }
+            None => ptr::null_mut(),
+        };
+        let access_flags = match options.access_flags {
+            Some(access_flags) => {
+                flags |= ibverbs_sys::IBV_REREG_MR_CHANGE_ACCESS;
+                access_flags.bits() as ffi::c_int
+            }
+            None => 0,
+        };
+        if flags == 0 {
+            return Err(custom_error("nothing to re-register"));
+        }
+
+        // SAFETY: ffi
+        let ret = unsafe {
+            let flags = flags as ffi::c_int;
+            ibverbs_sys::ibv_rereg_mr(self.ffi_ptr(), flags, pd, addr, length, access_flags)
+        };
+        if ret != 0 {
+            return Err(rereg_error(ret));
+        }
+
+        if let Some((addr, _)) = options.translation {
+            let iova = ptr_to_addr(addr).numeric_cast();
+            self.0.iova.store(iova, atomic::Ordering::Relaxed);
+        }
+        if let Some(pd) = options.pd {
+            *self.0.lock_pd() = pd;
+        }
+
+        Ok(ReregReport {
+            old_lkey,
+            old_rkey,
+            lkey: self.lkey(),
+            rkey: self.rkey(),
+        })
+    }
+
+    /// Returns the virtual address by which the memory region is accessed.
+    ///
+    /// It equals [`MemoryRegion::addr_u64`] unless the memory region is registered with an IOVA.
+    #[inline]
+    #[must_use]
+    pub fn iova(&self) -> u64 {
+        self.0.iova.load(atomic::Ordering::Relaxed)
+    }
+
     #[inline]
     #[must_use]
     pub fn length(&self) -> usize {
@@ -93,12 +346,120 @@ impl<T> MemoryRegion<T> {
     }
 }
 
+/// A memory region which owns its buffer.
+///
+/// The buffer is deregistered before it is dropped.
+pub struct OwnedMemoryRegion<B: RegisteredBuffer> {
+    mr: MemoryRegion,
+    buf: B,
+}
+
+impl<B: RegisteredBuffer> OwnedMemoryRegion<B> {
+    /// Registers the whole buffer as a memory region associated with the protection domain `pd`.
+    ///
+    /// # Panics
+    /// + if the byte length of the buffer overflows
+    #[inline]
+    pub fn register(
+        pd: &ProtectionDomain,
+        mut buf: B,
+        access_flags: AccessFlags,
+    ) -> io::Result<Self> {
+        let addr = buf.as_mut_ptr().cast::<u8>();
+        let length = mem::size_of::<B::Item>().checked_mul(buf.len()).unwrap();
+        // SAFETY: the buffer is initialized and stays at the same address until it is dropped,
+        // which happens after the memory region is deregistered
+        let mr = unsafe { MemoryRegion::register(pd, addr, length, access_flags, ())? };
+        Ok(Self { mr, buf })
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn lkey(&self) -> u32 {
+        self.mr.lkey()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn rkey(&self) -> u32 {
+        self.mr.rkey()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn addr_u64(&self) -> u64 {
+        self.mr.addr_u64()
+    }
+
+    /// Returns the length of the memory region in bytes.
+    #[inline]
+    #[must_use]
+    pub fn length(&self) -> usize {
+        self.mr.length()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn as_slice(&self) -> &[B::Item] {
+        // SAFETY: guaranteed by `RegisteredBuffer`
+        unsafe { slice::from_raw_parts(self.buf.as_ptr(), self.buf.len()) }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn as_mut_slice(&mut self) -> &mut [B::Item] {
+        // SAFETY: guaranteed by `RegisteredBuffer`
+        unsafe { slice::from_raw_parts_mut(self.buf.as_mut_ptr(), self.buf.len()) }
+    }
+
+    /// Returns a scatter/gather element of the items in `range`.
+    ///
+    /// Returns `None` if the range is out of bounds or its byte length overflows `u32`.
+    #[inline]
+    #[must_use]
+    pub fn sge(&self, range: impl RangeBounds<usize>) -> Option<Sge> {
+        let len = self.buf.len();
+        let start = match range.start_bound() {
+            Bound::Included(&n) => n,
+            Bound::Excluded(&n) => n.checked_add(1)?,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&n) => n.checked_add(1)?,
+            Bound::Excluded(&n) => n,
+            Bound::Unbounded => len,
+        };
+        if start > end || end > len {
+            return None;
+        }
+        let item_size = mem::size_of::<B::Item>();
+        let offset = u64::try_from(start.checked_mul(item_size)?).ok()?;
+        let length = u32::try_from((end - start).checked_mul(item_size)?).ok()?;
+        Some(Sge {
+            addr: self.addr_u64().checked_add(offset)?,
+            length,
+            lkey: self.lkey(),
+        })
+    }
+
+    /// Deregisters the memory region and returns the buffer.
+    #[inline]
+    #[must_use]
+    pub fn into_buffer(self) -> B {
+        let Self { mr, buf } = self;
+        drop(mr);
+        buf
+    }
+}
+
 struct Owner<T> {
     mr: ptr::NonNull<ibverbs_sys::ibv_mr>,
+    iova: AtomicU64,
 
     metadata: T,
 
-    _pd: ProtectionDomain,
+    pd: sync::Mutex<ProtectionDomain>,
+    _dm: Option<DeviceMemory>,
 }
 
 /// SAFETY: owned type
@@ -114,6 +475,12 @@ impl<T> Owner<T> {
     fn metadata(&self) -> &T {
         &self.metadata
     }
+
+    /// The lock is only held to read or replace the handle,
+    /// so a poisoned lock still holds a valid protection domain.
+    fn lock_pd(&self) -> sync::MutexGuard<'_, ProtectionDomain> {
+        self.pd.lock().unwrap_or_else(sync::PoisonError::into_inner)
+    }
 }
 
 impl<T> Drop for Owner<T> {
@@ -127,6 +494,107 @@ impl<T> Drop for Owner<T> {
     }
 }
 
+fn rereg_error(ret: ffi::c_int) -> io::Error {
+    let msg = match ret {
+        ibverbs_sys::IBV_REREG_MR_ERR_INPUT => {
+            return io::Error::new(io::ErrorKind::InvalidInput, "invalid re-registration input");
+        }
+        ibverbs_sys::IBV_REREG_MR_ERR_DONT_FORK_NEW => "failed to protect the new range from fork",
+        ibverbs_sys::IBV_REREG_MR_ERR_DO_FORK_OLD => "failed to restore fork of the old range",
+        ibverbs_sys::IBV_REREG_MR_ERR_CMD => "failed to re-register memory region",
+        ibverbs_sys::IBV_REREG_MR_ERR_CMD_AND_DO_FORK_NEW => {
+            "failed to re-register memory region and to restore fork of the new range"
+        }
+        _ => return from_errno(ret.wrapping_neg()),
+    };
+    custom_error(msg)
+}
+
+/// The options of [`MemoryRegion::rereg`]
+#[derive(Default)]
+pub struct ReregOptions {
+    translation: Option<(*mut u8, usize)>,
+    pd: Option<ProtectionDomain>,
+    access_flags: Option<AccessFlags>,
+}
+
+impl ReregOptions {
+    /// Changes the starting address and the size.
+    #[inline]
+    pub fn translation(&mut self, addr: *mut u8, length: usize) -> &mut Self {
+        self.translation = Some((addr, length));
+        self
+    }
+
+    #[inline]
+    pub fn pd(&mut self, pd: &ProtectionDomain) -> &mut Self {
+        self.pd = Some(pd.clone());
+        self
+    }
+
+    #[inline]
+    pub fn access_flags(&mut self, access_flags: AccessFlags) -> &mut Self {
+        self.access_flags = Some(access_flags);
+        self
+    }
+}
+
+/// The keys before and after [`MemoryRegion::rereg`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReregReport {
+    old_lkey: u32,
+    old_rkey: u32,
+    lkey: u32,
+    rkey: u32,
+}
+
+impl ReregReport {
+    #[inline]
+    #[must_use]
+    pub fn lkey(&self) -> u32 {
+        self.lkey
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn rkey(&self) -> u32 {
+        self.rkey
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn lkey_changed(&self) -> bool {
+        self.old_lkey != self.lkey
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn rkey_changed(&self) -> bool {
+        self.old_rkey != self.rkey
+    }
+}
+
+/// The advice of [`MemoryRegion::advise`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdviseMr {
+    /// Prefetches the pages for reading
+    Prefetch,
+    /// Prefetches the pages for writing
+    PrefetchWrite,
+    /// Prefetches the pages without faulting in the pages which are not present
+    PrefetchNoFault,
+}
+
+impl AdviseMr {
+    fn to_raw(self) -> ibverbs_sys::ib_uverbs_advise_mr_advice {
+        match self {
+            AdviseMr::Prefetch => ibverbs_sys::IB_UVERBS_ADVISE_MR_ADVICE_PREFETCH,
+            AdviseMr::PrefetchWrite => ibverbs_sys::IB_UVERBS_ADVISE_MR_ADVICE_PREFETCH_WRITE,
+            AdviseMr::PrefetchNoFault => ibverbs_sys::IB_UVERBS_ADVISE_MR_ADVICE_PREFETCH_NO_FAULT,
+        }
+    }
+}
+
 bitflags::bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
         pub struct AccessFlags: u32 {
@@ -141,3 +609,9 @@ bitflags::bitflags! {
             const RELAXED_ORDERING  = ibv_access_flags::IBV_ACCESS_RELAXED_ORDERING.0;
         }
 }
+
+impl AccessFlags {
+    pub(crate) fn to_c_uint(self) -> ffi::c_uint {
+        self.bits()
+    }
+}
@@ -1,7 +1,13 @@
-use crate::error::create_resource;
+use crate::error::{create_resource, from_errno};
+use crate::mr::{AccessFlags, MemoryRegion};
 use crate::pd::ProtectionDomain;
+use crate::qp::QueuePair;
+use crate::wr::SendFlags;
 
-use std::{ffi, io, ptr, sync};
+use std::ops::Range;
+use std::{ffi, io, mem, ptr, sync};
+
+use numeric_cast::NumericCast;
 
 #[derive(Clone)]
 pub struct MemoryWindow(sync::Arc<Owner>);
@@ -23,6 +29,116 @@ impl MemoryWindow {
         };
         Ok(Self(owner))
     }
+
+    pub(crate) fn ffi_ptr(&self) -> *mut ibverbs_sys::ibv_mw {
+        self.0.ffi_ptr()
+    }
+
+    /// Returns the current rkey of the memory window.
+    #[inline]
+    #[must_use]
+    pub fn rkey(&self) -> u32 {
+        let mw = self.ffi_ptr();
+        // SAFETY: reading a field of a concurrent ffi type
+        unsafe { (*mw).rkey }
+    }
+
+    /// Returns the rkey for the next type 2 binding, whose key tag is incremented.
+    #[inline]
+    #[must_use]
+    pub fn next_rkey(&self) -> u32 {
+        inc_rkey(self.rkey())
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn mw_type(&self) -> MemoryWindowType {
+        let mw = self.ffi_ptr();
+        // SAFETY: reading a immutable field of a concurrent ffi type
+        match unsafe { (*mw).type_ } {
+            ibverbs_sys::IBV_MW_TYPE_1 => MemoryWindowType::Type1,
+            _ => MemoryWindowType::Type2,
+        }
+    }
+
+    /// Binds the type 1 memory window by posting a bind work request on `qp`.
+    ///
+    /// Returns the new rkey, which becomes valid when the work request completes.
+    /// An [`MwBindInfo::unbind`] invalidates the window.
+    ///
+    /// # Safety
+    /// the memory region of `bind_info` must stay registered while the window is bound
+    #[inline]
+    pub unsafe fn bind(
+        &self,
+        qp: &QueuePair,
+        bind_info: &MwBindInfo,
+        wr_id: u64,
+        send_flags: SendFlags,
+    ) -> io::Result<u32> {
+        // SAFETY: ffi
+        unsafe {
+            let mut mw_bind = ibverbs_sys::ibv_mw_bind {
+                wr_id,
+                send_flags: send_flags.bits(),
+                bind_info: bind_info.0,
+            };
+            let ret = ibverbs_sys::ibv_bind_mw(qp.ffi_ptr(), self.ffi_ptr(), &mut mw_bind);
+            if ret != 0 {
+                return Err(from_errno(ret));
+            }
+        }
+        Ok(self.rkey())
+    }
+}
+
+/// `ibv_inc_rkey`
+fn inc_rkey(rkey: u32) -> u32 {
+    const MASK: u32 = 0x0000_00ff;
+    let tag = rkey.wrapping_add(1) & MASK;
+    (rkey & !MASK) | tag
+}
+
+/// The range of a memory region that a memory window grants access to
+#[repr(transparent)]
+pub struct MwBindInfo(pub(crate) ibverbs_sys::ibv_mw_bind_info);
+
+/// SAFETY: ffi pointer data
+/// the actual usage is unsafe (`C::ibv_bind_mw`)
+unsafe impl Send for MwBindInfo {}
+/// SAFETY: ffi pointer data
+/// the actual usage is unsafe (`C::ibv_bind_mw`)
+unsafe impl Sync for MwBindInfo {}
+
+impl MwBindInfo {
+    /// Grants `access_flags` over `range`, which is relative to the starting address of `mr`.
+    ///
+    /// Returns `None` if the range is out of bounds.
+    #[inline]
+    #[must_use]
+    pub fn new<T>(
+        mr: &MemoryRegion<T>,
+        range: Range<usize>,
+        access_flags: AccessFlags,
+    ) -> Option<Self> {
+        if range.start > range.end || range.end > mr.length() {
+            return None;
+        }
+        Some(Self(ibverbs_sys::ibv_mw_bind_info {
+            mr: mr.ffi_ptr(),
+            addr: mr.iova().checked_add(range.start.numeric_cast())?,
+            length: (range.end - range.start).numeric_cast(),
+            mw_access_flags: access_flags.to_c_uint(),
+        }))
+    }
+
+    /// Unbinds the memory window.
+    #[inline]
+    #[must_use]
+    pub fn unbind() -> Self {
+        // SAFETY: POD ffi type
+        unsafe { Self(mem::zeroed()) }
+    }
 }
 
 struct Owner {
@@ -36,7 +152,7 @@ unsafe impl Send for Owner {}
 unsafe impl Sync for Owner {}
 
 impl Owner {
-    fn ffi_ptr(&self) -> *mut ibverbs_sys::ibv_mw {
+    pub(crate) fn ffi_ptr(&self) -> *mut ibverbs_sys::ibv_mw {
         self.mw.as_ptr()
     }
 }
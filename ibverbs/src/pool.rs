@@ -0,0 +1,314 @@
+//! A pool of small registered buffers.
+//!
+//! The pool registers large chunks once and carves them into buffers of fixed size classes.
+//!
+//! The pool only grows: a chunk stays registered until the pool and all of its buffers
+//! are dropped, even if all buffers of the chunk are free.
+//! Once `max_chunks` chunks are registered, allocating from a size class
+//! without free buffers fails, while freed buffers are still reused.
+
+use crate::buf::{AlignedBuffer, RegisteredBuffer};
+use crate::error::custom_error;
+use crate::mr::{AccessFlags, MemoryRegion};
+use crate::pd::ProtectionDomain;
+use crate::wr::Sge;
+
+use std::sync::atomic::{self, AtomicUsize};
+use std::{io, slice, sync};
+
+use numeric_cast::NumericCast;
+
+#[derive(Clone)]
+pub struct MemoryPool(sync::Arc<Inner>);
+
+struct Inner {
+    pd: ProtectionDomain,
+    access_flags: AccessFlags,
+    chunk_size: usize,
+    max_chunks: Option<usize>,
+    num_chunks: AtomicUsize,
+    classes: Vec<SizeClass>,
+}
+
+struct SizeClass {
+    size: usize,
+    free: sync::Mutex<Vec<Slot>>,
+}
+
+struct Slot {
+    chunk: MemoryRegion<AlignedBuffer>,
+    offset: usize,
+}
+
+impl MemoryPool {
+    #[inline]
+    #[must_use]
+    pub fn options() -> MemoryPoolOptions {
+        MemoryPoolOptions::default()
+    }
+
+    /// Creates an empty pool whose chunks are registered in the protection domain `pd`.
+    ///
+    /// Fails if there is no size class, or if a size class is zero or exceeds `u32::MAX`.
+    #[inline]
+    pub fn create(pd: &ProtectionDomain, options: MemoryPoolOptions) -> io::Result<Self> {
+        let mut sizes = options.size_classes;
+        sizes.sort_unstable();
+        sizes.dedup();
+        if sizes.is_empty() || sizes[0] == 0 {
+            return Err(custom_error("size classes must be non-empty and non-zero"));
+        }
+        // the length of a scatter/gather element is `u32`
+        if sizes
+            .last()
+            .is_some_and(|&size| u32::try_from(size).is_err())
+        {
+            return Err(custom_error("size classes must not exceed u32::MAX"));
+        }
+        let classes = sizes
+            .into_iter()
+            .map(|size| SizeClass {
+                size,
+                free: sync::Mutex::new(Vec::new()),
+            })
+            .collect();
+        Ok(Self(sync::Arc::new(Inner {
+            pd: pd.clone(),
+            access_flags: options.access_flags,
+            chunk_size: options.chunk_size,
+            max_chunks: options.max_chunks,
+            num_chunks: AtomicUsize::new(0),
+            classes,
+        })))
+    }
+
+    /// Allocates a buffer of `len` bytes from the smallest size class which fits.
+    ///
+    /// A new chunk is registered if the size class has no free buffer.
+    #[inline]
+    pub fn alloc(&self, len: usize) -> io::Result<PooledBuffer> {
+        let Some(class) = self.0.classes.iter().position(|c| c.size >= len) else {
+            return Err(custom_error("the size exceeds the largest size class"));
+        };
+        let slot = self.0.alloc_slot(class)?;
+        Ok(PooledBuffer {
+            slot: Some(slot),
+            len,
+            class,
+            pool: sync::Arc::clone(&self.0),
+        })
+    }
+
+    /// Returns the number of registered chunks.
+    #[inline]
+    #[must_use]
+    pub fn num_chunks(&self) -> usize {
+        self.0.num_chunks.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Returns the size classes in ascending order.
+    #[inline]
+    #[must_use]
+    pub fn size_classes(&self) -> Vec<usize> {
+        self.0.classes.iter().map(|c| c.size).collect()
+    }
+}
+
+impl Inner {
+    fn alloc_slot(&self, class: usize) -> io::Result<Slot> {
+        let size_class = &self.classes[class];
+        if let Some(slot) = size_class.free.lock().unwrap().pop() {
+            return Ok(slot);
+        }
+        // register without holding the lock, since it may take a long time
+        let chunk = self.register_chunk(size_class.size)?;
+        let count = chunk.length() / size_class.size;
+        let mut free = size_class.free.lock().unwrap();
+        free.extend((1..count).rev().map(|i| Slot {
+            chunk: chunk.clone(),
+            offset: i * size_class.size,
+        }));
+        Ok(Slot { chunk, offset: 0 })
+    }
+
+    fn register_chunk(&self, size: usize) -> io::Result<MemoryRegion<AlignedBuffer>> {
+        let reserved = self.num_chunks.fetch_update(
+            atomic::Ordering::Relaxed,
+            atomic::Ordering::Relaxed,
+            |n| match self.max_chunks {
+                Some(max) if n >= max => None,
+                _ => Some(n + 1),
+            },
+        );
+        if reserved.is_err() {
+            return Err(custom_error("the memory pool is exhausted"));
+        }
+
+        let chunk_len = if size >= self.chunk_size {
+            size
+        } else {
+            self.chunk_size - self.chunk_size % size
+        };
+        let mut buf = AlignedBuffer::new(chunk_len);
+        let addr = buf.as_mut_ptr();
+        // SAFETY: the buffer is initialized and owned by the memory region
+        let ret =
+            unsafe { MemoryRegion::register(&self.pd, addr, chunk_len, self.access_flags, buf) };
+        if ret.is_err() {
+            self.num_chunks.fetch_sub(1, atomic::Ordering::Relaxed);
+        }
+        ret
+    }
+}
+
+/// A buffer allocated from [`MemoryPool`]
+///
+/// The buffer is returned to the pool when it is dropped.
+pub struct PooledBuffer {
+    slot: Option<Slot>,
+    len: usize,
+    class: usize,
+    pool: sync::Arc<Inner>,
+}
+
+impl PooledBuffer {
+    fn slot(&self) -> &Slot {
+        self.slot.as_ref().unwrap()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn lkey(&self) -> u32 {
+        self.slot().chunk.lkey()
+    }
+
+    /// Returns the remote key of the whole chunk.
+    ///
+    /// **The key is not limited to this buffer.**
+    /// A peer which knows it can access every buffer in the same chunk,
+    /// including the ones allocated to other users of the pool.
+    /// Only share it with peers which are trusted with the whole pool.
+    #[inline]
+    #[must_use]
+    pub fn rkey(&self) -> u32 {
+        self.slot().chunk.rkey()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn addr_u64(&self) -> u64 {
+        let slot = self.slot();
+        slot.chunk.addr_u64() + slot.offset.numeric_cast::<u64>()
+    }
+
+    /// Returns the requested length.
+    #[inline]
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the size of the size class.
+    #[inline]
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.pool.classes[self.class].size
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn as_slice(&self) -> &[u8] {
+        let slot = self.slot();
+        // SAFETY: the range is owned by the buffer
+        unsafe { slice::from_raw_parts(slot.chunk.addr_ptr().add(slot.offset), self.len) }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        let slot = self.slot();
+        // SAFETY: the range is owned by the buffer
+        unsafe { slice::from_raw_parts_mut(slot.chunk.addr_ptr().add(slot.offset), self.len) }
+    }
+
+    /// Returns a scatter/gather element of the whole buffer.
+    #[inline]
+    #[must_use]
+    pub fn sge(&self) -> Sge {
+        Sge {
+            addr: self.addr_u64(),
+            length: self.len.numeric_cast(),
+            lkey: self.lkey(),
+        }
+    }
+}
+
+impl Drop for PooledBuffer {
+    #[inline]
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot.take() {
+            let mut free = self.pool.classes[self.class].free.lock().unwrap();
+            free.push(slot);
+        }
+    }
+}
+
+pub struct MemoryPoolOptions {
+    size_classes: Vec<usize>,
+    chunk_size: usize,
+    max_chunks: Option<usize>,
+    access_flags: AccessFlags,
+}
+
+impl Default for MemoryPoolOptions {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            size_classes: vec![64, 256, 1024, 4096, 16384, 65536],
+            chunk_size: 2 * 1024 * 1024,
+            max_chunks: None,
+            access_flags: AccessFlags::LOCAL_WRITE,
+        }
+    }
+}
+
+impl MemoryPoolOptions {
+    #[inline]
+    pub fn size_classes(&mut self, size_classes: &[usize]) -> &mut Self {
+        self.size_classes = size_classes.to_vec();
+        self
+    }
+
+    /// Sets the size of a registered chunk.
+    /// A size class larger than the chunk size registers one buffer per chunk.
+    #[inline]
+    pub fn chunk_size(&mut self, chunk_size: usize) -> &mut Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Sets the limit of registered chunks over all size classes.
+    ///
+    /// Chunks are never released, so the limit caps the memory of the pool for its lifetime.
+    #[inline]
+    pub fn max_chunks(&mut self, max_chunks: usize) -> &mut Self {
+        self.max_chunks = Some(max_chunks);
+        self
+    }
+
+    /// Sets the access flags of the chunks, which is `LOCAL_WRITE` by default.
+    ///
+    /// Remote access is granted per chunk, not per buffer,
+    /// so see [`PooledBuffer::rkey`] before enabling it.
+    #[inline]
+    pub fn access_flags(&mut self, access_flags: AccessFlags) -> &mut Self {
+        self.access_flags = access_flags;
+        self
+    }
+}
@@ -1,17 +1,17 @@
-use crate::ah::AddressHandleOptions;
+use crate::ah::{AddressHandleOptions, GlobalRoute};
 use crate::cq::CompletionQueue;
 use crate::ctx::Context;
-use crate::device::Mtu;
-use crate::error::{create_resource, from_errno, get_errno, set_errno};
+use crate::device::{DeviceAttr, Gid, Mtu};
+use crate::error::{create_resource, custom_error, from_errno, get_errno, set_errno};
 use crate::mr::AccessFlags;
 use crate::pd::ProtectionDomain;
 use crate::srq::SharedReceiveQueue;
 use crate::utils::ptr_as_mut;
-use crate::utils::{usize_to_void_ptr, void_ptr_to_usize};
-use crate::wr::{RecvRequest, SendRequest};
+use crate::wr::{RecvRequest, SendBatch, SendRequest};
+use crate::xrcd::XrcDomain;
 
 use ibverbs_sys::{ibv_qp_attr_mask, ibv_qp_state};
-use std::{ffi, io, mem, ptr, sync};
+use std::{error, ffi, fmt, io, mem, ptr, sync};
 
 #[derive(Clone)]
 pub struct QueuePair(sync::Arc<Owner>);
@@ -39,15 +39,96 @@ impl QueuePair {
                 || "failed to create queue pair",
             )?;
 
+            let qp_ex = if qp_attr.comp_mask & ibverbs_sys::IBV_QP_INIT_ATTR_SEND_OPS_FLAGS != 0 {
+                ptr::NonNull::new(ibverbs_sys::ibv_qp_to_qp_ex(qp.as_ptr()))
+            } else {
+                None
+            };
+
             sync::Arc::new(Owner {
                 qp,
+                qp_ex,
                 _pd: options.pd,
                 send_cq: options.send_cq,
                 recv_cq: options.recv_cq,
                 _srq: options.srq,
+                _xrcd: options.xrcd,
+                user_data: options.user_data,
+                send_lock: sync::Mutex::new(()),
+                ctx: ctx.clone(),
             })
         };
-        Ok(Self(owner))
+        Ok(Self::register(ctx, owner))
+    }
+
+    fn register(ctx: &Context, owner: sync::Arc<Owner>) -> Self {
+        ctx.add_qp_ref(sync::Arc::downgrade(&owner));
+
+        // SAFETY: setup self-reference in qp_context
+        unsafe {
+            let owner_ptr: *const Owner = &*owner;
+            let qp = owner.ffi_ptr();
+            (*qp).qp_context = ptr_as_mut(owner_ptr).cast();
+        }
+
+        Self(owner)
+    }
+
+    /// Returns `None` if the queue pair is being destroyed,
+    /// or if the context is still null because an event arrives before `create` sets it.
+    ///
+    /// # SAFETY
+    /// 1. `qp_context` must come from the pointee of `QueuePair::ffi_ptr`
+    /// 2. there must be at least one weak reference to the queue pair owner
+    pub(crate) unsafe fn upgrade_qp_context(qp_context: *mut ffi::c_void) -> Option<Self> {
+        if qp_context.is_null() {
+            return None;
+        }
+        let owner_ptr: *const Owner = qp_context.cast();
+        // SAFETY: guaranteed by caller
+        let weak = mem::ManuallyDrop::new(unsafe { sync::Weak::from_raw(owner_ptr) });
+        sync::Weak::upgrade(&weak).map(Self)
+    }
+
+    /// Opens the XRC target queue pair `qp_num` of the XRC domain,
+    /// which may have been created by another process sharing the domain.
+    #[inline]
+    pub fn open_xrc(
+        ctx: &Context,
+        xrcd: &XrcDomain,
+        qp_num: u32,
+        user_data: usize,
+    ) -> io::Result<Self> {
+        // SAFETY: ffi
+        let owner = unsafe {
+            let context = ctx.ffi_ptr();
+            let mut attr: ibverbs_sys::ibv_qp_open_attr = mem::zeroed();
+            attr.comp_mask = ibverbs_sys::IBV_QP_OPEN_ATTR_NUM
+                | ibverbs_sys::IBV_QP_OPEN_ATTR_XRCD
+                | ibverbs_sys::IBV_QP_OPEN_ATTR_TYPE;
+            attr.qp_num = qp_num;
+            attr.xrcd = xrcd.ffi_ptr();
+            attr.qp_type = ibverbs_sys::ibv_qp_type::IBV_QPT_XRC_RECV;
+
+            let qp = create_resource(
+                || ibverbs_sys::ibv_open_qp(context, &mut attr),
+                || "failed to open queue pair",
+            )?;
+
+            sync::Arc::new(Owner {
+                qp,
+                qp_ex: None,
+                _pd: None,
+                send_cq: None,
+                recv_cq: None,
+                _srq: None,
+                _xrcd: Some(xrcd.clone()),
+                user_data,
+                send_lock: sync::Mutex::new(()),
+                ctx: ctx.clone(),
+            })
+        };
+        Ok(Self::register(ctx, owner))
     }
 
     #[inline]
@@ -61,9 +142,18 @@ impl QueuePair {
     #[inline]
     #[must_use]
     pub fn user_data(&self) -> usize {
+        self.0.user_data
+    }
+
+    /// # Panics
+    /// + if the queue pair type is unknown
+    #[inline]
+    #[must_use]
+    pub fn qp_type(&self) -> QueuePairType {
         let qp = self.ffi_ptr();
         // SAFETY: reading a immutable field of a concurrent ffi type
-        unsafe { void_ptr_to_usize((*qp).qp_context) }
+        let qp_type = unsafe { (*qp).qp_type };
+        QueuePairType::try_from(qp_type).expect("unknown queue pair type")
     }
 
     /// # Safety
@@ -104,6 +194,32 @@ impl QueuePair {
         Ok(())
     }
 
+    /// Starts a batch of work requests on the send queue.
+    ///
+    /// The queue pair must be created with [`QueuePairOptions::send_ops_flags`].
+    /// The batch is posted by [`SendBatch::commit`] and aborted if it is dropped.
+    ///
+    /// Only one batch can be built on a queue pair at a time,
+    /// so it blocks until the batch of another clone or thread ends.
+    /// Starting a second batch on the same thread may deadlock or panic.
+    #[inline]
+    pub fn send_batch(&self) -> io::Result<SendBatch<'_>> {
+        let qp_ex = self
+            .0
+            .qp_ex
+            .ok_or_else(|| custom_error("the queue pair is not created with send ops flags"))?;
+        // a panicking batch is aborted on unwind, which leaves the queue pair consistent
+        let guard = self
+            .0
+            .send_lock
+            .lock()
+            .unwrap_or_else(sync::PoisonError::into_inner);
+        // SAFETY:
+        // 1. the extended queue pair lives as long as the borrow of `self`
+        // 2. the guard is shared by all clones of the queue pair
+        unsafe { Ok(SendBatch::start(qp_ex, guard)) }
+    }
+
     #[inline]
     pub fn modify(&self, mut options: ModifyOptions) -> io::Result<()> {
         let qp = self.ffi_ptr();
@@ -119,6 +235,88 @@ impl QueuePair {
         }
     }
 
+    /// Modifies the queue pair from `Reset` to `Initialize`.
+    #[inline]
+    pub fn to_init(&self, attr: &InitAttr) -> io::Result<()> {
+        let mut options = ModifyOptions::default();
+        options
+            .qp_state(QueuePairState::Initialize)
+            .pkey_index(attr.pkey_index)
+            .port_num(attr.port_num);
+        if let Some(access_flags) = attr.access_flags {
+            options.qp_access_flags(access_flags);
+        }
+        if let Some(qkey) = attr.qkey {
+            options.qkey(qkey);
+        }
+        self.modify_checked(QueuePairState::Initialize, options)
+    }
+
+    /// Modifies the queue pair from `Initialize` to `ReadyToReceive`.
+    #[inline]
+    pub fn to_rtr(&self, attr: &RtrAttr) -> io::Result<()> {
+        let mut options = ModifyOptions::default();
+        options.qp_state(QueuePairState::ReadyToReceive);
+        if let Some(ref remote) = attr.remote {
+            options.dest_qp_num(remote.qp_num).rq_psn(remote.psn);
+        }
+        if let Some(ref path) = attr.path {
+            options.path_mtu(path.mtu);
+        }
+        if let (Some(remote), Some(path)) = (&attr.remote, &attr.path) {
+            let mut ah_attr = AddressHandleOptions::default();
+            ah_attr
+                .dest_lid(remote.lid)
+                .service_level(path.service_level)
+                .port_num(path.port_num);
+            if let Some(dest_gid) = remote.gid {
+                ah_attr.global_route_header(GlobalRoute {
+                    dest_gid,
+                    flow_label: path.flow_label,
+                    sgid_index: path.sgid_index,
+                    hop_limit: path.hop_limit,
+                    traffic_class: path.traffic_class,
+                });
+            }
+            options.ah_attr(ah_attr);
+        }
+        if let Some(max_dest_rd_atomic) = attr.max_dest_rd_atomic {
+            options.max_dest_rd_atomic(max_dest_rd_atomic);
+        }
+        if let Some(min_rnr_timer) = attr.min_rnr_timer {
+            options.min_rnr_timer(min_rnr_timer);
+        }
+        self.modify_checked(QueuePairState::ReadyToReceive, options)
+    }
+
+    /// Modifies the queue pair from `ReadyToReceive` to `ReadyToSend`.
+    #[inline]
+    pub fn to_rts(&self, attr: &RtsAttr) -> io::Result<()> {
+        let mut options = ModifyOptions::default();
+        options
+            .qp_state(QueuePairState::ReadyToSend)
+            .sq_psn(attr.sq_psn);
+        if let Some(timeout) = attr.timeout {
+            options.timeout(timeout);
+        }
+        if let Some(retry_cnt) = attr.retry_cnt {
+            options.retry_cnt(retry_cnt);
+        }
+        if let Some(rnr_retry) = attr.rnr_retry {
+            options.rnr_retry(rnr_retry);
+        }
+        if let Some(max_rd_atomic) = attr.max_rd_atomic {
+            options.max_rd_atomic(max_rd_atomic);
+        }
+        self.modify_checked(QueuePairState::ReadyToSend, options)
+    }
+
+    /// Checks the attributes against the transition table of the IB spec before modifying.
+    fn modify_checked(&self, qp_state: QueuePairState, options: ModifyOptions) -> io::Result<()> {
+        check_transition(self.qp_type(), qp_state, options.mask.0).map_err(custom_error)?;
+        self.modify(options)
+    }
+
     #[inline]
     pub fn query(&self, options: QueryOptions) -> io::Result<QueuePairAttr> {
         let qp = self.ffi_ptr();
@@ -149,13 +347,19 @@ impl QueuePair {
     }
 }
 
-struct Owner {
+pub(crate) struct Owner {
     qp: ptr::NonNull<ibverbs_sys::ibv_qp>,
+    qp_ex: Option<ptr::NonNull<ibverbs_sys::ibv_qp_ex>>,
+    user_data: usize,
+    /// Serializes the batches on `qp_ex`
+    send_lock: sync::Mutex<()>,
 
     _pd: Option<ProtectionDomain>,
     send_cq: Option<CompletionQueue>,
     recv_cq: Option<CompletionQueue>,
     _srq: Option<SharedReceiveQueue>,
+    _xrcd: Option<XrcDomain>,
+    ctx: Context,
 }
 
 /// SAFETY: owned type
@@ -177,6 +381,8 @@ impl Drop for Owner {
             let ret = ibverbs_sys::ibv_destroy_qp(qp);
             assert_eq!(ret, 0);
         }
+        // the owner stays reachable until the affiliated async events are acknowledged
+        assert!(self.ctx.del_qp_ref(self));
     }
 }
 
@@ -207,6 +413,19 @@ impl QueuePairCapacity {
         // SAFETY: same repr
         unsafe { mem::transmute(cap) }
     }
+
+    /// Clamps the numbers of work requests and scatter/gather elements to the device limits.
+    ///
+    /// `max_inline_data` is not reported by the device and is kept as is.
+    #[inline]
+    #[must_use]
+    pub fn clamp(mut self, attr: &DeviceAttr) -> Self {
+        self.max_send_wr = self.max_send_wr.min(attr.max_qp_wr());
+        self.max_recv_wr = self.max_recv_wr.min(attr.max_qp_wr());
+        self.max_send_sge = self.max_send_sge.min(attr.max_sge());
+        self.max_recv_sge = self.max_recv_sge.min(attr.max_sge());
+        self
+    }
 }
 
 pub struct QueuePairOptions {
@@ -216,6 +435,8 @@ pub struct QueuePairOptions {
     recv_cq: Option<CompletionQueue>,
     pd: Option<ProtectionDomain>,
     srq: Option<SharedReceiveQueue>,
+    xrcd: Option<XrcDomain>,
+    user_data: usize,
 }
 
 // SAFETY: owned type
@@ -233,6 +454,8 @@ impl Default for QueuePairOptions {
             recv_cq: None,
             pd: None,
             srq: None,
+            xrcd: None,
+            user_data: 0,
         }
     }
 }
@@ -240,7 +463,7 @@ impl Default for QueuePairOptions {
 impl QueuePairOptions {
     #[inline]
     pub fn user_data(&mut self, user_data: usize) -> &mut Self {
-        self.attr.qp_context = usize_to_void_ptr(user_data);
+        self.user_data = user_data;
         self
     }
 
@@ -296,6 +519,40 @@ impl QueuePairOptions {
         self.srq = Some(srq.clone());
         self
     }
+
+    /// Creates an XRC target queue pair in the XRC domain.
+    #[inline]
+    pub fn xrcd(&mut self, xrcd: &XrcDomain) -> &mut Self {
+        self.attr.xrcd = xrcd.ffi_ptr();
+        self.attr.comp_mask |= ibverbs_sys::IBV_QP_INIT_ATTR_XRCD;
+        self.xrcd = Some(xrcd.clone());
+        self
+    }
+
+    /// Creates an extended queue pair which supports the given opcodes in [`SendBatch`].
+    #[inline]
+    pub fn send_ops_flags(&mut self, send_ops_flags: SendOpsFlags) -> &mut Self {
+        self.attr.send_ops_flags = u64::from(send_ops_flags.bits());
+        self.attr.comp_mask |= ibverbs_sys::IBV_QP_INIT_ATTR_SEND_OPS_FLAGS;
+        self
+    }
+}
+
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct SendOpsFlags: u32 {
+        const RDMA_WRITE            = ibverbs_sys::IBV_QP_EX_WITH_RDMA_WRITE;
+        const RDMA_WRITE_WITH_IMM   = ibverbs_sys::IBV_QP_EX_WITH_RDMA_WRITE_WITH_IMM;
+        const SEND                  = ibverbs_sys::IBV_QP_EX_WITH_SEND;
+        const SEND_WITH_IMM         = ibverbs_sys::IBV_QP_EX_WITH_SEND_WITH_IMM;
+        const RDMA_READ             = ibverbs_sys::IBV_QP_EX_WITH_RDMA_READ;
+        const ATOMIC_CMP_AND_SWP    = ibverbs_sys::IBV_QP_EX_WITH_ATOMIC_CMP_AND_SWP;
+        const ATOMIC_FETCH_AND_ADD  = ibverbs_sys::IBV_QP_EX_WITH_ATOMIC_FETCH_AND_ADD;
+        const LOCAL_INV             = ibverbs_sys::IBV_QP_EX_WITH_LOCAL_INV;
+        const BIND_MW               = ibverbs_sys::IBV_QP_EX_WITH_BIND_MW;
+        const SEND_WITH_INV         = ibverbs_sys::IBV_QP_EX_WITH_SEND_WITH_INV;
+        const TSO                   = ibverbs_sys::IBV_QP_EX_WITH_TSO;
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -309,6 +566,240 @@ pub enum QueuePairType {
     XrcSend = ibverbs_sys::ibv_qp_type::IBV_QPT_XRC_SEND,
 }
 
+impl TryFrom<ffi::c_uint> for QueuePairType {
+    type Error = ();
+
+    fn try_from(value: ffi::c_uint) -> Result<Self, Self::Error> {
+        use ibverbs_sys::ibv_qp_type::*;
+        match value {
+            IBV_QPT_RC => Ok(QueuePairType::RC),
+            IBV_QPT_UC => Ok(QueuePairType::UC),
+            IBV_QPT_UD => Ok(QueuePairType::UD),
+            IBV_QPT_DRIVER => Ok(QueuePairType::Driver),
+            IBV_QPT_XRC_RECV => Ok(QueuePairType::XrcRecv),
+            IBV_QPT_XRC_SEND => Ok(QueuePairType::XrcSend),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Attributes of the transition from `Reset` to `Initialize`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InitAttr {
+    pub port_num: u8,
+    pub pkey_index: u16,
+    /// Required by RC, UC and XRC queue pairs
+    pub access_flags: Option<AccessFlags>,
+    /// Required by UD queue pairs
+    pub qkey: Option<u32>,
+}
+
+/// The remote side of a connected queue pair
+#[derive(Clone, Copy)]
+pub struct RemoteEndpoint {
+    pub qp_num: u32,
+    pub psn: u32,
+    pub lid: u16,
+    /// Required by RoCE and by routing across subnets
+    pub gid: Option<Gid>,
+}
+
+/// The local path to a remote endpoint
+#[derive(Debug, Clone, Copy)]
+pub struct PathAttr {
+    pub port_num: u8,
+    pub mtu: Mtu,
+    pub sgid_index: u8,
+    pub service_level: u8,
+    pub hop_limit: u8,
+    pub traffic_class: u8,
+    pub flow_label: u32,
+}
+
+/// Attributes of the transition from `Initialize` to `ReadyToReceive`
+///
+/// Connected queue pairs require `remote` and `path`.
+/// RC queue pairs also require `max_dest_rd_atomic` and `min_rnr_timer`.
+#[derive(Clone, Copy, Default)]
+pub struct RtrAttr {
+    pub remote: Option<RemoteEndpoint>,
+    pub path: Option<PathAttr>,
+    pub max_dest_rd_atomic: Option<u8>,
+    pub min_rnr_timer: Option<u8>,
+}
+
+/// Attributes of the transition from `ReadyToReceive` to `ReadyToSend`
+///
+/// RC queue pairs require all fields.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RtsAttr {
+    pub sq_psn: u32,
+    pub timeout: Option<u8>,
+    pub retry_cnt: Option<u8>,
+    pub rnr_retry: Option<u8>,
+    pub max_rd_atomic: Option<u8>,
+}
+
+/// Returns the required and optional attributes of a transition.
+fn transition_mask(qp_type: QueuePairType, qp_state: QueuePairState) -> Option<(u32, u32)> {
+    use QueuePairState::*;
+    use QueuePairType::*;
+    use ibverbs_sys::ibv_qp_attr_mask as M;
+
+    let init = M::IBV_QP_PKEY_INDEX.0 | M::IBV_QP_PORT.0;
+    let rtr_uc = M::IBV_QP_AV.0 | M::IBV_QP_PATH_MTU.0 | M::IBV_QP_DEST_QPN.0 | M::IBV_QP_RQ_PSN.0;
+    let rtr_rc = rtr_uc | M::IBV_QP_MAX_DEST_RD_ATOMIC.0 | M::IBV_QP_MIN_RNR_TIMER.0;
+    let rtr_opt = M::IBV_QP_ALT_PATH.0 | M::IBV_QP_ACCESS_FLAGS.0 | M::IBV_QP_PKEY_INDEX.0;
+    let rts_rc = M::IBV_QP_TIMEOUT.0
+        | M::IBV_QP_RETRY_CNT.0
+        | M::IBV_QP_RNR_RETRY.0
+        | M::IBV_QP_SQ_PSN.0
+        | M::IBV_QP_MAX_QP_RD_ATOMIC.0;
+    let rts_opt = M::IBV_QP_CUR_STATE.0
+        | M::IBV_QP_ALT_PATH.0
+        | M::IBV_QP_ACCESS_FLAGS.0
+        | M::IBV_QP_PATH_MIG_STATE.0;
+
+    let mask = match (qp_state, qp_type) {
+        (Initialize, UD) => (init | M::IBV_QP_QKEY.0, 0),
+        (Initialize, RC | UC | XrcSend | XrcRecv) => (init | M::IBV_QP_ACCESS_FLAGS.0, 0),
+        (ReadyToReceive, UD) => (0, M::IBV_QP_PKEY_INDEX.0 | M::IBV_QP_QKEY.0),
+        (ReadyToReceive, UC | XrcSend) => (rtr_uc, rtr_opt),
+        (ReadyToReceive, RC | XrcRecv) => (rtr_rc, rtr_opt),
+        (ReadyToSend, UD) => (M::IBV_QP_SQ_PSN.0, M::IBV_QP_CUR_STATE.0 | M::IBV_QP_QKEY.0),
+        (ReadyToSend, UC) => (M::IBV_QP_SQ_PSN.0, rts_opt),
+        (ReadyToSend, RC) => (rts_rc, rts_opt | M::IBV_QP_MIN_RNR_TIMER.0),
+        (ReadyToSend, XrcSend) => (rts_rc, rts_opt),
+        (ReadyToSend, XrcRecv) => (
+            M::IBV_QP_TIMEOUT.0 | M::IBV_QP_SQ_PSN.0,
+            rts_opt | M::IBV_QP_MIN_RNR_TIMER.0,
+        ),
+        _ => return None,
+    };
+    Some(mask)
+}
+
+/// Checks the attribute mask of a transition, where `IBV_QP_STATE` is always required.
+fn check_transition(
+    qp_type: QueuePairType,
+    qp_state: QueuePairState,
+    mask: u32,
+) -> Result<(), TransitionError> {
+    let Some((required, optional)) = transition_mask(qp_type, qp_state) else {
+        return Err(TransitionError {
+            qp_type,
+            qp_state,
+            missing: Vec::new(),
+            unexpected: Vec::new(),
+        });
+    };
+    let required = required | ibv_qp_attr_mask::IBV_QP_STATE.0;
+    let missing = required & !mask;
+    let unexpected = mask & !(required | optional);
+    if missing != 0 || unexpected != 0 {
+        return Err(TransitionError {
+            qp_type,
+            qp_state,
+            missing: mask_names(missing),
+            unexpected: mask_names(unexpected),
+        });
+    }
+    Ok(())
+}
+
+fn mask_names(mask: u32) -> Vec<&'static str> {
+    use ibverbs_sys::ibv_qp_attr_mask as M;
+
+    let names = [
+        (M::IBV_QP_STATE, "qp_state"),
+        (M::IBV_QP_CUR_STATE, "cur_qp_state"),
+        (M::IBV_QP_EN_SQD_ASYNC_NOTIFY, "en_sqd_async_notify"),
+        (M::IBV_QP_ACCESS_FLAGS, "qp_access_flags"),
+        (M::IBV_QP_PKEY_INDEX, "pkey_index"),
+        (M::IBV_QP_PORT, "port_num"),
+        (M::IBV_QP_QKEY, "qkey"),
+        (M::IBV_QP_AV, "ah_attr"),
+        (M::IBV_QP_PATH_MTU, "path_mtu"),
+        (M::IBV_QP_TIMEOUT, "timeout"),
+        (M::IBV_QP_RETRY_CNT, "retry_cnt"),
+        (M::IBV_QP_RNR_RETRY, "rnr_retry"),
+        (M::IBV_QP_RQ_PSN, "rq_psn"),
+        (M::IBV_QP_MAX_QP_RD_ATOMIC, "max_rd_atomic"),
+        (M::IBV_QP_ALT_PATH, "alt_ah_attr"),
+        (M::IBV_QP_MIN_RNR_TIMER, "min_rnr_timer"),
+        (M::IBV_QP_SQ_PSN, "sq_psn"),
+        (M::IBV_QP_MAX_DEST_RD_ATOMIC, "max_dest_rd_atomic"),
+        (M::IBV_QP_PATH_MIG_STATE, "path_mig_state"),
+        (M::IBV_QP_CAP, "cap"),
+        (M::IBV_QP_DEST_QPN, "dest_qp_num"),
+    ];
+    names
+        .into_iter()
+        .filter(|(m, _)| mask & m.0 != 0)
+        .map(|(_, name)| name)
+        .collect()
+}
+
+/// An invalid combination of attributes in a queue pair transition
+#[derive(Debug)]
+pub struct TransitionError {
+    qp_type: QueuePairType,
+    qp_state: QueuePairState,
+    missing: Vec<&'static str>,
+    unexpected: Vec<&'static str>,
+}
+
+impl TransitionError {
+    #[inline]
+    #[must_use]
+    pub fn qp_type(&self) -> QueuePairType {
+        self.qp_type
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn qp_state(&self) -> QueuePairState {
+        self.qp_state
+    }
+
+    /// Returns the names of the required attributes which are not set.
+    #[inline]
+    #[must_use]
+    pub fn missing(&self) -> &[&'static str] {
+        &self.missing
+    }
+
+    /// Returns the names of the attributes which are not allowed in the transition.
+    #[inline]
+    #[must_use]
+    pub fn unexpected(&self) -> &[&'static str] {
+        &self.unexpected
+    }
+}
+
+impl fmt::Display for TransitionError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid transition of {:?} queue pair to {:?}",
+            self.qp_type, self.qp_state
+        )?;
+        if self.missing.is_empty() && self.unexpected.is_empty() {
+            return write!(f, ": unsupported transition");
+        }
+        if !self.missing.is_empty() {
+            write!(f, ": missing {}", self.missing.join(", "))?;
+        }
+        if !self.unexpected.is_empty() {
+            write!(f, ": unexpected {}", self.unexpected.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for TransitionError {}
+
 #[repr(C)]
 pub struct ModifyOptions {
     mask: ibverbs_sys::ibv_qp_attr_mask,
@@ -324,7 +815,7 @@ impl Default for ModifyOptions {
     #[inline]
     fn default() -> Self {
         Self {
-            mask: 0,
+            mask: ibv_qp_attr_mask(0),
             attr: mem::MaybeUninit::uninit(),
         }
     }
@@ -340,7 +831,7 @@ macro_rules! modify_option {
                 let p = ptr::addr_of_mut!((*attr).$field);
                 p.write($($cvt)+);
             }
-            self.mask |= ibverbs_sys::$mask;
+            self.mask |= ibv_qp_attr_mask::$mask;
             self
         }
     };
@@ -397,13 +888,13 @@ impl Default for QueryOptions {
 impl QueryOptions {
     #[inline]
     pub fn cap(&mut self) -> &mut Self {
-        self.mask |= ibverbs_sys::IBV_QP_CAP;
+        self.mask |= ibv_qp_attr_mask::IBV_QP_CAP;
         self
     }
 
     #[inline]
     pub fn qp_state(&mut self) -> &mut Self {
-        self.mask |= ibverbs_sys::IBV_QP_STATE;
+        self.mask |= ibv_qp_attr_mask::IBV_QP_STATE;
         self
     }
 }
@@ -423,7 +914,7 @@ impl QueuePairAttr {
     #[inline]
     #[must_use]
     pub fn cap(&self) -> Option<&QueuePairCapacity> {
-        (self.mask & ibverbs_sys::IBV_QP_CAP != 0)
+        (self.mask & ibv_qp_attr_mask::IBV_QP_CAP != ibv_qp_attr_mask(0))
             .then(|| QueuePairCapacity::from_ctype_ref(&self.attr.cap))
     }
 
@@ -451,6 +942,12 @@ pub enum QueuePairState {
     Unknown = ibv_qp_state::IBV_QPS_UNKNOWN, // ASK: what is this
 }
 
+impl QueuePairState {
+    pub(crate) fn to_c_uint(self) -> ffi::c_uint {
+        self as ffi::c_uint
+    }
+}
+
 impl TryFrom<ffi::c_uint> for QueuePairState {
     type Error = ();
 
@@ -468,3 +965,171 @@ impl TryFrom<ffi::c_uint> for QueuePairState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use QueuePairState::{Initialize, ReadyToReceive, ReadyToSend};
+    use QueuePairType::*;
+
+    // `enum ibv_qp_attr_mask` of rdma-core
+    const STATE: u32 = 1 << 0;
+    const CUR_STATE: u32 = 1 << 1;
+    const ACCESS_FLAGS: u32 = 1 << 3;
+    const PKEY_INDEX: u32 = 1 << 4;
+    const PORT: u32 = 1 << 5;
+    const QKEY: u32 = 1 << 6;
+    const AV: u32 = 1 << 7;
+    const PATH_MTU: u32 = 1 << 8;
+    const TIMEOUT: u32 = 1 << 9;
+    const RETRY_CNT: u32 = 1 << 10;
+    const RNR_RETRY: u32 = 1 << 11;
+    const RQ_PSN: u32 = 1 << 12;
+    const MAX_QP_RD_ATOMIC: u32 = 1 << 13;
+    const ALT_PATH: u32 = 1 << 14;
+    const MIN_RNR_TIMER: u32 = 1 << 15;
+    const SQ_PSN: u32 = 1 << 16;
+    const MAX_DEST_RD_ATOMIC: u32 = 1 << 17;
+    const PATH_MIG_STATE: u32 = 1 << 18;
+    const DEST_QPN: u32 = 1 << 20;
+
+    fn ok(qp_type: QueuePairType, qp_state: QueuePairState, mask: u32) -> bool {
+        check_transition(qp_type, qp_state, mask).is_ok()
+    }
+
+    /// The masks used by the pingpong examples of rdma-core
+    #[test]
+    fn pingpong() {
+        // ibv_rc_pingpong
+        assert!(ok(RC, Initialize, STATE | PKEY_INDEX | PORT | ACCESS_FLAGS));
+        let rtr = STATE | AV | PATH_MTU | DEST_QPN | RQ_PSN | MAX_DEST_RD_ATOMIC | MIN_RNR_TIMER;
+        assert!(ok(RC, ReadyToReceive, rtr));
+        let rts = STATE | TIMEOUT | RETRY_CNT | RNR_RETRY | SQ_PSN | MAX_QP_RD_ATOMIC;
+        assert!(ok(RC, ReadyToSend, rts));
+
+        // ibv_uc_pingpong
+        assert!(ok(UC, Initialize, STATE | PKEY_INDEX | PORT | ACCESS_FLAGS));
+        assert!(ok(
+            UC,
+            ReadyToReceive,
+            STATE | AV | PATH_MTU | DEST_QPN | RQ_PSN
+        ));
+        assert!(ok(UC, ReadyToSend, STATE | SQ_PSN));
+
+        // ibv_ud_pingpong
+        assert!(ok(UD, Initialize, STATE | PKEY_INDEX | PORT | QKEY));
+        assert!(ok(UD, ReadyToReceive, STATE));
+        assert!(ok(UD, ReadyToSend, STATE | SQ_PSN));
+
+        // ibv_xsrq_pingpong, the send side
+        assert!(ok(
+            XrcSend,
+            Initialize,
+            STATE | PKEY_INDEX | PORT | ACCESS_FLAGS
+        ));
+        assert!(ok(
+            XrcSend,
+            ReadyToReceive,
+            STATE | AV | PATH_MTU | DEST_QPN | RQ_PSN
+        ));
+        let rts = STATE | TIMEOUT | RETRY_CNT | RNR_RETRY | SQ_PSN | MAX_QP_RD_ATOMIC;
+        assert!(ok(XrcSend, ReadyToSend, rts));
+
+        // ibv_xsrq_pingpong, the receive side
+        assert!(ok(
+            XrcRecv,
+            Initialize,
+            STATE | PKEY_INDEX | PORT | ACCESS_FLAGS
+        ));
+        let rtr = STATE | AV | PATH_MTU | DEST_QPN | RQ_PSN | MAX_DEST_RD_ATOMIC | MIN_RNR_TIMER;
+        assert!(ok(XrcRecv, ReadyToReceive, rtr));
+        assert!(ok(XrcRecv, ReadyToSend, STATE | TIMEOUT | SQ_PSN));
+    }
+
+    /// The optional attributes of the kernel table `qp_state_table`
+    #[test]
+    fn optional() {
+        let rts_opt = CUR_STATE | ALT_PATH | ACCESS_FLAGS | PATH_MIG_STATE;
+        let rtr_opt = ALT_PATH | ACCESS_FLAGS | PKEY_INDEX;
+
+        let rtr_uc = STATE | AV | PATH_MTU | DEST_QPN | RQ_PSN;
+        let rtr_rc = rtr_uc | MAX_DEST_RD_ATOMIC | MIN_RNR_TIMER;
+        assert!(ok(RC, ReadyToReceive, rtr_rc | rtr_opt));
+        assert!(ok(UC, ReadyToReceive, rtr_uc | rtr_opt));
+        assert!(ok(XrcSend, ReadyToReceive, rtr_uc | rtr_opt));
+        assert!(ok(XrcRecv, ReadyToReceive, rtr_rc | rtr_opt));
+        assert!(ok(UD, ReadyToReceive, STATE | PKEY_INDEX | QKEY));
+
+        let rts_rc = STATE | TIMEOUT | RETRY_CNT | RNR_RETRY | SQ_PSN | MAX_QP_RD_ATOMIC;
+        assert!(ok(RC, ReadyToSend, rts_rc | rts_opt | MIN_RNR_TIMER));
+        assert!(ok(XrcSend, ReadyToSend, rts_rc | rts_opt));
+        let rts_tgt = STATE | TIMEOUT | SQ_PSN;
+        assert!(ok(XrcRecv, ReadyToSend, rts_tgt | rts_opt | MIN_RNR_TIMER));
+        assert!(ok(UC, ReadyToSend, STATE | SQ_PSN | rts_opt));
+        assert!(ok(UD, ReadyToSend, STATE | SQ_PSN | CUR_STATE | QKEY));
+    }
+
+    #[test]
+    fn invalid_transition() {
+        assert!(transition_mask(RC, QueuePairState::Reset).is_none());
+        assert!(transition_mask(RC, QueuePairState::SendQueueDrained).is_none());
+        assert!(transition_mask(Driver, Initialize).is_none());
+
+        let err = check_transition(UD, QueuePairState::Error, 0).unwrap_err();
+        assert!(err.missing().is_empty() && err.unexpected().is_empty());
+
+        // XRC_INI does not accept MIN_RNR_TIMER in RTR->RTS
+        let rts = STATE | TIMEOUT | RETRY_CNT | RNR_RETRY | SQ_PSN | MAX_QP_RD_ATOMIC;
+        let err = check_transition(XrcSend, ReadyToSend, rts | MIN_RNR_TIMER).unwrap_err();
+        assert!(err.missing().is_empty());
+        assert_eq!(err.unexpected(), ["min_rnr_timer"]);
+
+        // UC does not accept the RC attributes
+        assert!(!ok(UC, ReadyToSend, rts));
+        assert!(!ok(
+            UC,
+            ReadyToReceive,
+            STATE | AV | PATH_MTU | DEST_QPN | RQ_PSN | MIN_RNR_TIMER
+        ));
+
+        let rtr_uc = STATE | AV | PATH_MTU | DEST_QPN | RQ_PSN;
+        let err = check_transition(RC, ReadyToReceive, rtr_uc | QKEY).unwrap_err();
+        assert_eq!(err.missing(), ["min_rnr_timer", "max_dest_rd_atomic"]);
+        assert_eq!(err.unexpected(), ["qkey"]);
+
+        let err = check_transition(UD, Initialize, PKEY_INDEX | PORT).unwrap_err();
+        assert_eq!(err.missing(), ["qp_state", "qkey"]);
+        assert!(err.unexpected().is_empty());
+
+        assert!(!ok(
+            RC,
+            Initialize,
+            STATE | PKEY_INDEX | PORT | ACCESS_FLAGS | QKEY
+        ));
+    }
+
+    #[test]
+    fn clamp_capacity() {
+        let attr = ibverbs_sys::ibv_device_attr {
+            max_qp_wr: 1024,
+            max_sge: 4,
+            ..Default::default()
+        };
+        let attr = DeviceAttr::from_ctype_ref(&attr);
+
+        let cap = QueuePairCapacity {
+            max_send_wr: 4096,
+            max_recv_wr: 16,
+            max_send_sge: 1,
+            max_recv_sge: 30,
+            max_inline_data: 256,
+        };
+        let cap = cap.clamp(attr);
+        assert_eq!(cap.max_send_wr, 1024);
+        assert_eq!(cap.max_recv_wr, 16);
+        assert_eq!(cap.max_send_sge, 1);
+        assert_eq!(cap.max_recv_sge, 4);
+        assert_eq!(cap.max_inline_data, 256);
+    }
+}
@@ -1,9 +1,12 @@
+use crate::cq::CompletionQueue;
 use crate::ctx::Context;
-use crate::error::create_resource;
+use crate::error::{create_resource, from_errno, get_errno, set_errno};
 use crate::pd::ProtectionDomain;
-use crate::utils::usize_to_void_ptr;
+use crate::utils::ptr_as_mut;
+use crate::wr::RecvRequest;
+use crate::xrcd::XrcDomain;
 
-use std::{io, mem, ptr, sync};
+use std::{ffi, io, mem, ptr, sync};
 
 #[derive(Clone)]
 pub struct SharedReceiveQueue(sync::Arc<Owner>);
@@ -40,19 +43,124 @@ impl SharedReceiveQueue {
 
             sync::Arc::new(Owner {
                 srq,
-                _ctx: ctx.clone(),
+                user_data: options.user_data,
+                ctx: ctx.clone(),
                 _pd: options.pd,
+                _xrcd: options.xrcd,
+                _cq: options.cq,
             })
         };
+
+        ctx.add_srq_ref(sync::Arc::downgrade(&owner));
+
+        // SAFETY: setup self-reference in srq_context
+        unsafe {
+            let owner_ptr: *const Owner = &*owner;
+            let srq = owner.ffi_ptr();
+            (*srq).srq_context = ptr_as_mut(owner_ptr).cast();
+        }
+
         Ok(Self(owner))
     }
+
+    /// Returns `None` if the shared receive queue is being destroyed,
+    /// or if the context is still null because an event arrives before `create` sets it.
+    ///
+    /// # SAFETY
+    /// 1. `srq_context` must come from the pointee of `SharedReceiveQueue::ffi_ptr`
+    /// 2. there must be at least one weak reference to the shared receive queue owner
+    pub(crate) unsafe fn from_srq_context(srq_context: *mut ffi::c_void) -> Option<Self> {
+        if srq_context.is_null() {
+            return None;
+        }
+        let owner_ptr: *const Owner = srq_context.cast();
+        // SAFETY: guaranteed by caller
+        let weak = mem::ManuallyDrop::new(unsafe { sync::Weak::from_raw(owner_ptr) });
+        sync::Weak::upgrade(&weak).map(Self)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn user_data(&self) -> usize {
+        self.0.user_data
+    }
+
+    /// # Safety
+    /// the memory referenced by the request must be valid until the work request completes
+    #[inline]
+    pub unsafe fn post_recv(&self, recv_wr: &RecvRequest) -> io::Result<()> {
+        let srq = self.ffi_ptr();
+        let wr: *mut ibverbs_sys::ibv_recv_wr = ptr_as_mut(recv_wr).cast();
+        let mut bad_wr: *mut ibverbs_sys::ibv_recv_wr = ptr::null_mut();
+        // SAFETY: ffi
+        unsafe {
+            set_errno(0);
+            let ret = ibverbs_sys::ibv_post_srq_recv(srq, wr, &mut bad_wr);
+            if ret != 0 {
+                let errno = get_errno();
+                if errno != 0 {
+                    return Err(from_errno(errno));
+                }
+                return Err(from_errno(ret.abs()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Resizes the queue or arms the limit.
+    ///
+    /// When the number of outstanding receive requests drops below the armed limit,
+    /// [`AsyncEvent::SrqLimitReached`](crate::ctx::AsyncEvent::SrqLimitReached) is reported
+    /// and the limit is disarmed until it is set again.
+    #[inline]
+    pub fn modify(&self, mut options: ModifyOptions) -> io::Result<()> {
+        let srq = self.ffi_ptr();
+        // SAFETY: ffi
+        unsafe {
+            let ret = ibverbs_sys::ibv_modify_srq(srq, &mut options.attr, options.mask);
+            if ret != 0 {
+                return Err(from_errno(ret));
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn query(&self) -> io::Result<SharedReceiveQueueAttr> {
+        let srq = self.ffi_ptr();
+        // SAFETY: ffi
+        unsafe {
+            let mut attr: ibverbs_sys::ibv_srq_attr = mem::zeroed();
+            let ret = ibverbs_sys::ibv_query_srq(srq, &mut attr);
+            if ret != 0 {
+                return Err(from_errno(ret));
+            }
+            Ok(SharedReceiveQueueAttr(attr))
+        }
+    }
+
+    /// Returns the SRQ number, which is used by XRC.
+    #[inline]
+    pub fn srq_num(&self) -> io::Result<u32> {
+        let srq = self.ffi_ptr();
+        let mut srq_num = 0;
+        // SAFETY: ffi
+        let ret = unsafe { ibverbs_sys::ibv_get_srq_num(srq, &mut srq_num) };
+        if ret != 0 {
+            return Err(from_errno(ret));
+        }
+        Ok(srq_num)
+    }
 }
 
-struct Owner {
+pub(crate) struct Owner {
     srq: ptr::NonNull<ibverbs_sys::ibv_srq>,
+    user_data: usize,
 
-    _ctx: Context,
+    ctx: Context,
     _pd: Option<ProtectionDomain>,
+    _xrcd: Option<XrcDomain>,
+    _cq: Option<CompletionQueue>,
 }
 
 /// SAFETY: owned type
@@ -74,12 +182,17 @@ impl Drop for Owner {
             let ret = ibverbs_sys::ibv_destroy_srq(srq);
             assert_eq!(ret, 0);
         }
+        // the owner stays reachable until the affiliated async events are acknowledged
+        assert!(self.ctx.del_srq_ref(self));
     }
 }
 
 pub struct SharedReceiveQueueOptions {
     attr: ibverbs_sys::ibv_srq_init_attr_ex,
+    user_data: usize,
     pd: Option<ProtectionDomain>,
+    xrcd: Option<XrcDomain>,
+    cq: Option<CompletionQueue>,
 }
 
 impl Default for SharedReceiveQueueOptions {
@@ -88,7 +201,10 @@ impl Default for SharedReceiveQueueOptions {
         Self {
             // SAFETY: POD ffi type
             attr: unsafe { mem::zeroed() },
+            user_data: 0,
             pd: None,
+            xrcd: None,
+            cq: None,
         }
     }
 }
@@ -104,7 +220,93 @@ impl SharedReceiveQueueOptions {
 
     #[inline]
     pub fn user_data(&mut self, user_data: usize) -> &mut Self {
-        self.attr.srq_context = usize_to_void_ptr(user_data);
+        self.user_data = user_data;
+        self
+    }
+
+    #[inline]
+    pub fn max_wr(&mut self, max_wr: u32) -> &mut Self {
+        self.attr.attr.max_wr = max_wr;
         self
     }
+
+    #[inline]
+    pub fn max_sge(&mut self, max_sge: u32) -> &mut Self {
+        self.attr.attr.max_sge = max_sge;
+        self
+    }
+
+    #[inline]
+    pub fn srq_limit(&mut self, srq_limit: u32) -> &mut Self {
+        self.attr.attr.srq_limit = srq_limit;
+        self
+    }
+
+    /// Creates an XRC shared receive queue in the XRC domain,
+    /// whose receive completions are reported to `cq`.
+    ///
+    /// A protection domain is also required.
+    #[inline]
+    pub fn xrc(&mut self, xrcd: &XrcDomain, cq: &CompletionQueue) -> &mut Self {
+        self.attr.srq_type = ibverbs_sys::IBV_SRQT_XRC;
+        self.attr.xrcd = xrcd.ffi_ptr();
+        self.attr.cq = ibverbs_sys::ibv_cq_ex_to_cq(cq.ffi_ptr());
+        self.attr.comp_mask |= ibverbs_sys::IBV_SRQ_INIT_ATTR_TYPE
+            | ibverbs_sys::IBV_SRQ_INIT_ATTR_XRCD
+            | ibverbs_sys::IBV_SRQ_INIT_ATTR_CQ;
+        self.xrcd = Some(xrcd.clone());
+        self.cq = Some(cq.clone());
+        self
+    }
+}
+
+pub struct ModifyOptions {
+    mask: ffi::c_int,
+    attr: ibverbs_sys::ibv_srq_attr,
+}
+
+impl Default for ModifyOptions {
+    #[inline]
+    fn default() -> Self {
+        // SAFETY: POD ffi type
+        unsafe { mem::zeroed() }
+    }
+}
+
+impl ModifyOptions {
+    #[inline]
+    pub fn max_wr(&mut self, max_wr: u32) -> &mut Self {
+        self.attr.max_wr = max_wr;
+        self.mask |= ibverbs_sys::IBV_SRQ_MAX_WR as ffi::c_int;
+        self
+    }
+
+    #[inline]
+    pub fn srq_limit(&mut self, srq_limit: u32) -> &mut Self {
+        self.attr.srq_limit = srq_limit;
+        self.mask |= ibverbs_sys::IBV_SRQ_LIMIT as ffi::c_int;
+        self
+    }
+}
+
+pub struct SharedReceiveQueueAttr(ibverbs_sys::ibv_srq_attr);
+
+impl SharedReceiveQueueAttr {
+    #[inline]
+    #[must_use]
+    pub fn max_wr(&self) -> u32 {
+        self.0.max_wr
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn max_sge(&self) -> u32 {
+        self.0.max_sge
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn srq_limit(&self) -> u32 {
+        self.0.srq_limit
+    }
 }
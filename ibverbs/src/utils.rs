@@ -1,4 +1,8 @@
-use std::os::raw::{c_uint, c_void};
+use crate::error::last_error;
+
+use std::io;
+use std::os::raw::c_uint;
+use std::os::unix::prelude::RawFd;
 
 #[allow(clippy::unnecessary_cast)]
 pub const fn c_uint_to_u32(x: c_uint) -> u32 {
@@ -18,14 +22,25 @@ pub fn ptr_as_mut<T>(p: *const T) -> *mut T {
     p.cast_mut()
 }
 
-pub fn usize_to_void_ptr(val: usize) -> *mut c_void {
-    val as *mut c_void
-}
-
-pub fn void_ptr_to_usize(p: *mut c_void) -> usize {
-    p as usize
-}
-
 pub fn u32_as_c_uint(val: u32) -> c_uint {
     val as c_uint
 }
+
+pub fn set_nonblocking(fd: RawFd, nonblocking: bool) -> io::Result<()> {
+    // SAFETY: ffi
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags < 0 {
+            return Err(last_error());
+        }
+        let flags = if nonblocking {
+            flags | libc::O_NONBLOCK
+        } else {
+            flags & !libc::O_NONBLOCK
+        };
+        if libc::fcntl(fd, libc::F_SETFL, flags) < 0 {
+            return Err(last_error());
+        }
+    }
+    Ok(())
+}
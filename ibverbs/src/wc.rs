@@ -1,6 +1,7 @@
-use ibverbs_sys::{ibv_wc_opcode, ibv_wc_status};
+use ibverbs_sys::{ibv_wc_flags, ibv_wc_opcode, ibv_wc_status};
 use std::{ffi, fmt, mem};
 
+#[derive(Clone, Copy)]
 #[repr(transparent)]
 pub struct WorkCompletion(ibverbs_sys::ibv_wc);
 
@@ -29,17 +30,43 @@ impl WorkCompletion {
         Opcode::try_from(self.0.opcode).unwrap()
     }
 
+    #[inline]
+    #[must_use]
+    pub fn vendor_err(&self) -> u32 {
+        self.0.vendor_err
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn qp_num(&self) -> u32 {
+        self.0.qp_num
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn wc_flags(&self) -> WorkCompletionFlags {
+        WorkCompletionFlags::from_bits_truncate(self.0.wc_flags)
+    }
+
     #[inline]
     #[must_use]
     pub fn imm_data(&self) -> Option<u32> {
-        /*
+        let has_imm = self.wc_flags().contains(WorkCompletionFlags::WITH_IMM);
         // SAFETY: tagged union
-        unsafe {
-            let has_imm = self.0.wc_flags & C::IBV_WC_WITH_IMM != 0;
-            has_imm.then_some(self.0.__bindgen_anon_1.imm_data)
-        }
-        */
-        todo!()
+        has_imm.then(|| unsafe { self.0.__bindgen_anon_1.imm_data })
+    }
+}
+
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct WorkCompletionFlags: u32 {
+        const GRH           = ibv_wc_flags::IBV_WC_GRH.0;
+        const WITH_IMM      = ibv_wc_flags::IBV_WC_WITH_IMM.0;
+        const IP_CSUM_OK    = ibv_wc_flags::IBV_WC_IP_CSUM_OK.0;
+        const WITH_INV      = ibv_wc_flags::IBV_WC_WITH_INV.0;
+        const TM_SYNC_REQ   = ibv_wc_flags::IBV_WC_TM_SYNC_REQ.0;
+        const TM_MATCH      = ibv_wc_flags::IBV_WC_TM_MATCH.0;
+        const TM_DATA_VALID = ibv_wc_flags::IBV_WC_TM_DATA_VALID.0;
     }
 }
 
@@ -1,8 +1,10 @@
 use crate::ah::AddressHandle;
+use crate::error::from_errno;
+use crate::mw::{MemoryWindow, MwBindInfo};
 use crate::utils::ptr_as_mut;
 
 use ibverbs_sys::{ibv_send_flags, ibv_wr_opcode};
-use std::{ffi, mem};
+use std::{ffi, io, mem, ptr, sync};
 
 #[repr(transparent)]
 pub struct SendRequest(ibverbs_sys::ibv_send_wr);
@@ -112,9 +114,64 @@ impl SendRequest {
         self.0.__bindgen_anon_1.imm_data = imm_data;
         self
     }
+
+    /// Sets the remote XRC shared receive queue of an XRC send queue pair.
+    #[inline]
+    pub fn xrc_remote_srqn(&mut self, remote_srqn: u32) -> &mut Self {
+        self.0.qp_type.xrc.remote_srqn = remote_srqn;
+        self
+    }
+
+    /// Sets the rkey invalidated by [`Opcode::SendWithInv`] or [`Opcode::LocalInv`].
+    #[inline]
+    pub fn invalidate_rkey(&mut self, rkey: u32) -> &mut Self {
+        self.0.__bindgen_anon_1.invalidate_rkey = rkey;
+        self
+    }
+
+    /// Sets the type 2 memory window bound by [`Opcode::BindMw`].
+    ///
+    /// # Safety
+    /// 1. the memory window must be alive until the work request completes
+    /// 2. the memory region of `bind_info` must stay registered while the window is bound
+    #[inline]
+    pub unsafe fn bind_mw(
+        &mut self,
+        mw: &MemoryWindow,
+        rkey: u32,
+        bind_info: &MwBindInfo,
+    ) -> &mut Self {
+        // SAFETY: tagged union
+        unsafe {
+            let bind_mw = &mut self.0.__bindgen_anon_2.bind_mw;
+            bind_mw.mw = mw.ffi_ptr();
+            bind_mw.rkey = rkey;
+            bind_mw.bind_info = bind_info.0;
+        }
+        self
+    }
+
+    pub(crate) fn is_chained(&self) -> bool {
+        !self.0.next.is_null()
+    }
+
+    pub(crate) fn set_signaled(&mut self) {
+        self.0.send_flags |= ibv_send_flags::IBV_SEND_SIGNALED.0;
+    }
 }
 
 impl RecvRequest {
+    #[inline]
+    #[must_use]
+    pub fn zeroed() -> Self {
+        // SAFETY: POD ffi type
+        unsafe { Self(mem::zeroed()) }
+    }
+
+    pub(crate) fn is_chained(&self) -> bool {
+        !self.0.next.is_null()
+    }
+
     #[inline]
     pub fn id(&mut self, id: u64) -> &mut Self {
         self.0.wr_id = id;
@@ -135,6 +192,231 @@ impl RecvRequest {
     }
 }
 
+/// A batch of work requests built on an extended queue pair.
+///
+/// Each work request starts with an opcode method (e.g. [`SendBatch::send`]),
+/// which takes the current [`SendBatch::wr_id`] and [`SendBatch::wr_flags`],
+/// and is followed by its data (e.g. [`SendBatch::sg_list`]).
+///
+/// The batch holds the send lock of the queue pair until it is committed or aborted.
+pub struct SendBatch<'qp> {
+    qp_ex: ptr::NonNull<ibverbs_sys::ibv_qp_ex>,
+    committed: bool,
+    _guard: sync::MutexGuard<'qp, ()>,
+}
+
+impl<'qp> SendBatch<'qp> {
+    /// # Safety
+    /// + `qp_ex` must be valid until the batch is committed or aborted
+    /// + `guard` must serialize all batches of `qp_ex`
+    pub(crate) unsafe fn start(
+        qp_ex: ptr::NonNull<ibverbs_sys::ibv_qp_ex>,
+        guard: sync::MutexGuard<'qp, ()>,
+    ) -> Self {
+        // SAFETY: ffi
+        unsafe { ibverbs_sys::ibv_wr_start(qp_ex.as_ptr()) };
+        Self {
+            qp_ex,
+            committed: false,
+            _guard: guard,
+        }
+    }
+
+    fn ffi_ptr(&self) -> *mut ibverbs_sys::ibv_qp_ex {
+        self.qp_ex.as_ptr()
+    }
+
+    #[inline]
+    pub fn wr_id(&mut self, wr_id: u64) -> &mut Self {
+        // SAFETY: the fields are owned by the batch until it ends
+        unsafe { (*self.ffi_ptr()).wr_id = wr_id };
+        self
+    }
+
+    #[inline]
+    pub fn wr_flags(&mut self, wr_flags: SendFlags) -> &mut Self {
+        // SAFETY: the fields are owned by the batch until it ends
+        unsafe { (*self.ffi_ptr()).wr_flags = wr_flags.bits() };
+        self
+    }
+
+    #[inline]
+    pub fn send(&mut self) -> &mut Self {
+        // SAFETY: ffi
+        unsafe { ibverbs_sys::ibv_wr_send(self.ffi_ptr()) };
+        self
+    }
+
+    #[inline]
+    pub fn send_imm(&mut self, imm_data: u32) -> &mut Self {
+        // SAFETY: ffi
+        unsafe { ibverbs_sys::ibv_wr_send_imm(self.ffi_ptr(), imm_data) };
+        self
+    }
+
+    /// # Safety
+    /// the remote memory must be accessible by `rkey` until the work request completes
+    #[inline]
+    pub unsafe fn rdma_write(&mut self, rkey: u32, remote_addr: u64) -> &mut Self {
+        // SAFETY: ffi
+        unsafe { ibverbs_sys::ibv_wr_rdma_write(self.ffi_ptr(), rkey, remote_addr) };
+        self
+    }
+
+    /// # Safety
+    /// the remote memory must be accessible by `rkey` until the work request completes
+    #[inline]
+    pub unsafe fn rdma_write_imm(
+        &mut self,
+        rkey: u32,
+        remote_addr: u64,
+        imm_data: u32,
+    ) -> &mut Self {
+        // SAFETY: ffi
+        unsafe { ibverbs_sys::ibv_wr_rdma_write_imm(self.ffi_ptr(), rkey, remote_addr, imm_data) };
+        self
+    }
+
+    /// # Safety
+    /// the remote memory must be accessible by `rkey` until the work request completes
+    #[inline]
+    pub unsafe fn rdma_read(&mut self, rkey: u32, remote_addr: u64) -> &mut Self {
+        // SAFETY: ffi
+        unsafe { ibverbs_sys::ibv_wr_rdma_read(self.ffi_ptr(), rkey, remote_addr) };
+        self
+    }
+
+    /// # Safety
+    /// the remote memory must be accessible by `rkey` until the work request completes
+    #[inline]
+    pub unsafe fn atomic_cmp_swp(
+        &mut self,
+        rkey: u32,
+        remote_addr: u64,
+        compare: u64,
+        swap: u64,
+    ) -> &mut Self {
+        // SAFETY: ffi
+        unsafe {
+            ibverbs_sys::ibv_wr_atomic_cmp_swp(self.ffi_ptr(), rkey, remote_addr, compare, swap);
+        };
+        self
+    }
+
+    /// # Safety
+    /// the remote memory must be accessible by `rkey` until the work request completes
+    #[inline]
+    pub unsafe fn atomic_fetch_add(&mut self, rkey: u32, remote_addr: u64, add: u64) -> &mut Self {
+        // SAFETY: ffi
+        unsafe { ibverbs_sys::ibv_wr_atomic_fetch_add(self.ffi_ptr(), rkey, remote_addr, add) };
+        self
+    }
+
+    /// Sends a message which invalidates `invalidate_rkey` on the remote side.
+    #[inline]
+    pub fn send_inv(&mut self, invalidate_rkey: u32) -> &mut Self {
+        // SAFETY: ffi
+        unsafe { ibverbs_sys::ibv_wr_send_inv(self.ffi_ptr(), invalidate_rkey) };
+        self
+    }
+
+    /// Invalidates the local memory window or memory region of `invalidate_rkey`.
+    #[inline]
+    pub fn local_inv(&mut self, invalidate_rkey: u32) -> &mut Self {
+        // SAFETY: ffi
+        unsafe { ibverbs_sys::ibv_wr_local_inv(self.ffi_ptr(), invalidate_rkey) };
+        self
+    }
+
+    /// Binds the type 2 memory window with the new `rkey` (see [`MemoryWindow::next_rkey`]).
+    ///
+    /// # Safety
+    /// 1. the memory window must be alive until the work request completes
+    /// 2. the memory region of `bind_info` must stay registered while the window is bound
+    #[inline]
+    pub unsafe fn bind_mw(
+        &mut self,
+        mw: &MemoryWindow,
+        rkey: u32,
+        bind_info: &MwBindInfo,
+    ) -> &mut Self {
+        // SAFETY: ffi
+        unsafe { ibverbs_sys::ibv_wr_bind_mw(self.ffi_ptr(), mw.ffi_ptr(), rkey, &bind_info.0) };
+        self
+    }
+
+    /// Sets the remote XRC shared receive queue of an XRC send queue pair.
+    #[inline]
+    pub fn xrc_srqn(&mut self, remote_srqn: u32) -> &mut Self {
+        // SAFETY: ffi
+        unsafe { ibverbs_sys::ibv_wr_set_xrc_srqn(self.ffi_ptr(), remote_srqn) };
+        self
+    }
+
+    /// # Safety
+    /// the address handle must be alive until the work request completes
+    #[inline]
+    pub unsafe fn ud_addr(
+        &mut self,
+        ah: &AddressHandle,
+        remote_qpn: u32,
+        remote_qkey: u32,
+    ) -> &mut Self {
+        // SAFETY: ffi
+        unsafe {
+            ibverbs_sys::ibv_wr_set_ud_addr(self.ffi_ptr(), ah.ffi_ptr(), remote_qpn, remote_qkey);
+        };
+        self
+    }
+
+    /// # Safety
+    /// the local memory must be valid for the device to access until the work request completes
+    #[inline]
+    pub unsafe fn sg_list(&mut self, sg_list: &[Sge]) -> &mut Self {
+        let sg = sg_list.as_ptr().cast::<ibverbs_sys::ibv_sge>();
+        // SAFETY: ffi
+        unsafe { ibverbs_sys::ibv_wr_set_sge_list(self.ffi_ptr(), sg_list.len(), sg) };
+        self
+    }
+
+    /// Copies `data` into the work request.
+    #[inline]
+    pub fn inline_data(&mut self, data: &[u8]) -> &mut Self {
+        let addr = ptr_as_mut(data.as_ptr()).cast();
+        // SAFETY: ffi, the data is copied before returning
+        unsafe { ibverbs_sys::ibv_wr_set_inline_data(self.ffi_ptr(), addr, data.len()) };
+        self
+    }
+
+    /// Posts all work requests in the batch.
+    #[inline]
+    pub fn commit(mut self) -> io::Result<()> {
+        self.committed = true;
+        // SAFETY: ffi
+        let ret = unsafe { ibverbs_sys::ibv_wr_complete(self.ffi_ptr()) };
+        if ret != 0 {
+            return Err(from_errno(ret));
+        }
+        Ok(())
+    }
+
+    /// Discards all work requests in the batch.
+    #[inline]
+    pub fn abort(self) {
+        drop(self);
+    }
+}
+
+impl Drop for SendBatch<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        if !self.committed {
+            // SAFETY: ffi
+            unsafe { ibverbs_sys::ibv_wr_abort(self.ffi_ptr()) }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
 pub enum Opcode {
@@ -144,6 +426,9 @@ pub enum Opcode {
     Read = ibv_wr_opcode::IBV_WR_RDMA_READ as ffi::c_uint,
     AtomicFetchAdd = ibv_wr_opcode::IBV_WR_ATOMIC_FETCH_AND_ADD as ffi::c_uint,
     AtomicCAS = ibv_wr_opcode::IBV_WR_ATOMIC_CMP_AND_SWP as ffi::c_uint,
+    LocalInv = ibv_wr_opcode::IBV_WR_LOCAL_INV as ffi::c_uint,
+    BindMw = ibv_wr_opcode::IBV_WR_BIND_MW as ffi::c_uint,
+    SendWithInv = ibv_wr_opcode::IBV_WR_SEND_WITH_INV as ffi::c_uint,
 }
 
 bitflags::bitflags! {
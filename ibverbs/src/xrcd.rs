@@ -0,0 +1,104 @@
+use crate::ctx::Context;
+use crate::error::create_resource;
+
+use std::os::fd::{AsRawFd, BorrowedFd};
+use std::{ffi, io, ptr, sync};
+
+/// An XRC domain, which groups XRC shared receive queues and XRC target queue pairs
+#[derive(Clone)]
+pub struct XrcDomain(sync::Arc<Owner>);
+
+impl XrcDomain {
+    pub(crate) fn ffi_ptr(&self) -> *mut ibverbs_sys::ibv_xrcd {
+        self.0.ffi_ptr()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn options() -> XrcDomainOptions {
+        XrcDomainOptions::default()
+    }
+
+    #[inline]
+    pub fn open(ctx: &Context, mut options: XrcDomainOptions) -> io::Result<Self> {
+        // SAFETY: ffi
+        let owner = unsafe {
+            let context = ctx.ffi_ptr();
+            let attr = &mut options.attr;
+            let xrcd = create_resource(
+                || ibverbs_sys::ibv_open_xrcd(context, attr),
+                || "failed to open XRC domain",
+            )?;
+            sync::Arc::new(Owner {
+                xrcd,
+                _ctx: ctx.clone(),
+            })
+        };
+        Ok(Self(owner))
+    }
+}
+
+struct Owner {
+    xrcd: ptr::NonNull<ibverbs_sys::ibv_xrcd>,
+
+    _ctx: Context,
+}
+
+/// SAFETY: owned type
+unsafe impl Send for Owner {}
+/// SAFETY: owned type
+unsafe impl Sync for Owner {}
+
+impl Owner {
+    fn ffi_ptr(&self) -> *mut ibverbs_sys::ibv_xrcd {
+        self.xrcd.as_ptr()
+    }
+}
+
+impl Drop for Owner {
+    fn drop(&mut self) {
+        // SAFETY: ffi
+        unsafe {
+            let xrcd = self.ffi_ptr();
+            let ret = ibverbs_sys::ibv_close_xrcd(xrcd);
+            assert_eq!(ret, 0);
+        }
+    }
+}
+
+pub struct XrcDomainOptions {
+    attr: ibverbs_sys::ibv_xrcd_init_attr,
+}
+
+impl Default for XrcDomainOptions {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            attr: ibverbs_sys::ibv_xrcd_init_attr {
+                comp_mask: ibverbs_sys::IBV_XRCD_INIT_ATTR_FD
+                    | ibverbs_sys::IBV_XRCD_INIT_ATTR_OFLAGS,
+                fd: -1,
+                oflags: libc::O_CREAT,
+            },
+        }
+    }
+}
+
+impl XrcDomainOptions {
+    /// Shares the domain with the other processes which open the same file.
+    ///
+    /// The file is only used when the domain is opened.
+    #[inline]
+    pub fn fd(&mut self, fd: BorrowedFd<'_>) -> &mut Self {
+        self.attr.fd = fd.as_raw_fd();
+        self
+    }
+
+    /// Sets the open flags, which are `O_CREAT` by default.
+    /// `O_EXCL` fails if the domain of the file already exists.
+    #[inline]
+    pub fn oflags(&mut self, oflags: ffi::c_int) -> &mut Self {
+        self.attr.oflags = oflags;
+        self
+    }
+}
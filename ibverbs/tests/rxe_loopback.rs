@@ -0,0 +1,132 @@
+//! Runs a RC send/recv exchange over a soft-RoCE (rxe) device.
+//!
+//! ```text
+//! rdma link add rxe0 type rxe netdev <ifname>
+//! IBVERBS_TEST_DEVICE=rxe0 cargo test --test rxe_loopback -- --ignored
+//! ```
+
+use ibverbs::connect::{self, ConnectOptions};
+use ibverbs::cq::{CompletionQueue, CompletionQueueOptions};
+use ibverbs::ctx::Context;
+use ibverbs::device::{DeviceList, GidType};
+use ibverbs::mr::{AccessFlags, OwnedMemoryRegion};
+use ibverbs::pd::ProtectionDomain;
+use ibverbs::qp::{QueuePair, QueuePairCapacity, QueuePairType};
+use ibverbs::wc::{WorkCompletion, WorkCompletionError};
+use ibverbs::wr::{Opcode, RecvRequest, SendFlags, SendRequest};
+
+use std::mem::MaybeUninit;
+use std::time::{Duration, Instant};
+use std::{env, thread};
+
+const MSG: &[u8] = b"hello over rxe";
+const RECV_WRID: u64 = 1;
+const SEND_WRID: u64 = 2;
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn open_device() -> Context {
+    let devices = DeviceList::available().unwrap();
+    let wanted = env::var("IBVERBS_TEST_DEVICE").ok();
+    let device = devices
+        .as_slice()
+        .iter()
+        .find(|d| match wanted {
+            Some(ref name) => d.name() == name.as_str(),
+            None => d.name().starts_with("rxe"),
+        })
+        .expect("no rxe device, set IBVERBS_TEST_DEVICE");
+    Context::open(device).unwrap()
+}
+
+fn create_qp(ctx: &Context, pd: &ProtectionDomain, cq: &CompletionQueue) -> QueuePair {
+    let mut options = QueuePair::options();
+    options
+        .send_cq(cq)
+        .recv_cq(cq)
+        .pd(pd)
+        .qp_type(QueuePairType::RC)
+        .cap(QueuePairCapacity {
+            max_send_wr: 4,
+            max_recv_wr: 4,
+            max_send_sge: 1,
+            max_recv_sge: 1,
+            max_inline_data: 0,
+        });
+    QueuePair::create(ctx, options).unwrap()
+}
+
+fn create_cq(ctx: &Context) -> CompletionQueue {
+    let mut options = CompletionQueueOptions::default();
+    options.cqe(8);
+    CompletionQueue::create(ctx, options).unwrap()
+}
+
+fn wait_for(cq: &CompletionQueue, wr_id: u64) {
+    let mut buf = [MaybeUninit::<WorkCompletion>::uninit(); 4];
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        if let Some(wc) = cq.poll(&mut buf).unwrap().first() {
+            WorkCompletionError::result(wc.status()).unwrap();
+            assert_eq!(wc.wr_id(), wr_id);
+            return;
+        }
+        assert!(Instant::now() < deadline, "timed out waiting for {wr_id}");
+        thread::yield_now();
+    }
+}
+
+#[test]
+#[ignore = "requires a soft-RoCE device"]
+fn send_recv() {
+    let ctx = open_device();
+    let gid_index = ctx
+        .gid_table(1)
+        .unwrap()
+        .iter()
+        .find(|e| e.gid_type() == GidType::RoceV2 && e.gid().to_ipv4_addr().is_some())
+        .map(|e| u8::try_from(e.gid_index()).unwrap())
+        .expect("no IPv4 RoCE v2 gid on port 1");
+
+    let pd = ProtectionDomain::alloc(&ctx).unwrap();
+    let server_cq = create_cq(&ctx);
+    let client_cq = create_cq(&ctx);
+    let server_qp = create_qp(&ctx, &pd, &server_cq);
+    let client_qp = create_qp(&ctx, &pd, &client_cq);
+
+    let recv_mr =
+        OwnedMemoryRegion::register(&pd, vec![0_u8; MSG.len()], AccessFlags::LOCAL_WRITE).unwrap();
+    let send_mr = OwnedMemoryRegion::register(&pd, MSG.to_vec(), AccessFlags::LOCAL_WRITE).unwrap();
+
+    let recv_sg_list = [recv_mr.sge(..).unwrap()];
+    let mut recv_wr = RecvRequest::zeroed();
+    recv_wr.id(RECV_WRID).sg_list(&recv_sg_list);
+    // SAFETY: the buffer outlives the work request, which is waited below
+    unsafe { server_qp.post_recv(&recv_wr).unwrap() };
+
+    let mut options = ConnectOptions::default();
+    options.gid_index(gid_index);
+
+    let listener = connect::listen("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (server, client) = thread::scope(|s| {
+        let server = s.spawn(|| connect::accept(&listener, &ctx, &server_qp, &options).unwrap());
+        let client = connect::connect(addr, &ctx, &client_qp, &options).unwrap();
+        (server.join().unwrap(), client)
+    });
+    assert_eq!(server.remote().qp_num, client_qp.qp_num());
+    assert_eq!(client.remote().qp_num, server_qp.qp_num());
+
+    let send_sg_list = [send_mr.sge(..).unwrap()];
+    let mut send_wr = SendRequest::zeroed();
+    send_wr
+        .id(SEND_WRID)
+        .sg_list(&send_sg_list)
+        .opcode(Opcode::Send)
+        .send_flags(SendFlags::SIGNALED);
+    // SAFETY: the buffer outlives the work request, which is waited below
+    unsafe { client_qp.post_send(&send_wr).unwrap() };
+
+    wait_for(&client_cq, SEND_WRID);
+    wait_for(&server_cq, RECV_WRID);
+    assert_eq!(recv_mr.as_slice(), MSG);
+}